@@ -0,0 +1,46 @@
+use crate::Transaction;
+use crate::storage::{read_json_file, write_json_file};
+use std::io;
+use tokio::sync::RwLock;
+
+/// Transactions moved out of the live ledger for age, kept in their own file.
+pub struct Archive {
+    file_path: String,
+    pub transactions: RwLock<Vec<Transaction>>,
+}
+
+impl Archive {
+    pub async fn load(file_path: impl Into<String>) -> io::Result<Self> {
+        let file_path = file_path.into();
+        let transactions = read_json_file(&file_path).await?.unwrap_or_default();
+        Ok(Self {
+            file_path,
+            transactions: RwLock::new(transactions),
+        })
+    }
+
+    /// The lowest sequence number no archived row uses, so the live ledger's
+    /// new rows can start there.
+    pub async fn next_seq(&self) -> u64 {
+        self.transactions
+            .read()
+            .await
+            .iter()
+            .map(|t| t.seq + 1)
+            .max()
+            .unwrap_or(1)
+    }
+
+    /// Append `moved` and write the whole archive out; on failure the archive
+    /// is left as it was.
+    pub async fn append(&self, moved: Vec<Transaction>) -> io::Result<()> {
+        let mut archived = self.transactions.write().await;
+        let before = archived.len();
+        archived.extend(moved);
+        let result = write_json_file(&self.file_path, &*archived).await;
+        if result.is_err() {
+            archived.truncate(before);
+        }
+        result
+    }
+}
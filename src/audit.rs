@@ -0,0 +1,180 @@
+use crate::Transaction;
+use crate::events::ChangeKind;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io;
+use tokio::fs::{self, OpenOptions};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::RwLock;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+/// One recorded change to one transaction.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct AuditEntry {
+    pub transaction_id: Uuid,
+    pub action: ChangeKind,
+    /// UNIX seconds when the change was recorded
+    pub at: u64,
+    /// None for the first change seen for this id
+    pub before: Option<Transaction>,
+    /// None once the transaction was removed outright
+    pub after: Option<Transaction>,
+}
+
+#[derive(Default)]
+struct Entries {
+    /// oldest first
+    all: Vec<AuditEntry>,
+    by_id: HashMap<Uuid, Vec<usize>>,
+}
+
+impl Entries {
+    fn push(&mut self, entry: AuditEntry) {
+        self.by_id
+            .entry(entry.transaction_id)
+            .or_default()
+            .push(self.all.len());
+        self.all.push(entry);
+    }
+}
+
+/// Append-only change log, one JSON object per line. An entry's `before` is
+/// the previous entry's `after` for the same id.
+pub struct AuditLog {
+    file_path: String,
+    entries: RwLock<Entries>,
+}
+
+impl AuditLog {
+    pub async fn load(file_path: impl Into<String>) -> io::Result<Self> {
+        let file_path = file_path.into();
+        let mut entries = Entries::default();
+        match fs::read_to_string(&file_path).await {
+            Ok(data) => {
+                for (n, line) in data.lines().enumerate() {
+                    if line.trim().is_empty() {
+                        continue;
+                    }
+                    match serde_json::from_str(line) {
+                        Ok(entry) => entries.push(entry),
+                        // most likely a line torn by a crash mid-append; the rest is still good
+                        Err(e) => tracing::warn!(
+                            error = %e,
+                            file = %file_path,
+                            line = n + 1,
+                            "Skipping unreadable audit entry"
+                        ),
+                    }
+                }
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+        Ok(Self {
+            file_path,
+            entries: RwLock::new(entries),
+        })
+    }
+
+    /// Record that `tx` changed; `after` is left empty for removals.
+    pub async fn record(&self, action: ChangeKind, tx: &Transaction) -> io::Result<()> {
+        let mut entries = self.entries.write().await;
+        let before = entries
+            .by_id
+            .get(&tx.id)
+            .and_then(|positions| positions.last())
+            .and_then(|&pos| entries.all[pos].after.clone());
+        let entry = AuditEntry {
+            transaction_id: tx.id,
+            action,
+            at: crate::now_secs(),
+            before,
+            after: (action != ChangeKind::Removed).then(|| tx.clone()),
+        };
+        let mut line = serde_json::to_vec(&entry)?;
+        line.push(b'\n');
+        // holding the lock across the append keeps file order and memory order the same
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.file_path)
+            .await?;
+        file.write_all(&line).await?;
+        entries.push(entry);
+        Ok(())
+    }
+
+    /// Every entry for one transaction, oldest first.
+    pub async fn history(&self, id: &Uuid) -> Vec<AuditEntry> {
+        let entries = self.entries.read().await;
+        entries
+            .by_id
+            .get(id)
+            .into_iter()
+            .flatten()
+            .map(|&pos| entries.all[pos].clone())
+            .collect()
+    }
+}
+
+/// One transaction that changed during a diff window.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct Modified {
+    pub before: Transaction,
+    pub after: Transaction,
+}
+
+/// Net effect of the audit log between two times. A transaction appears at
+/// most once: what it looked like at `from` is compared with what it looked
+/// like at `to`, so one created and deleted in between doesn't appear at all.
+/// Soft-deleted transactions count as absent, so a restore shows up as created.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct Diff {
+    pub from: u64,
+    pub to: u64,
+    /// as they were at `to`
+    pub created: Vec<Transaction>,
+    pub updated: Vec<Modified>,
+    /// as they were just before being deleted
+    pub deleted: Vec<Transaction>,
+}
+
+impl AuditLog {
+    /// What changed between `from` and `to`, both inclusive UNIX seconds.
+    pub async fn diff(&self, from: u64, to: u64) -> Diff {
+        let entries = self.entries.read().await;
+        // (state before the window, state at its end), in order of first change
+        let mut changed: Vec<(Option<Transaction>, Option<Transaction>)> = Vec::new();
+        let mut slot: HashMap<Uuid, usize> = HashMap::new();
+        for entry in entries.all.iter().filter(|e| (from..=to).contains(&e.at)) {
+            let live = |t: &Option<Transaction>| t.clone().filter(|t| !t.deleted);
+            match slot.get(&entry.transaction_id) {
+                Some(&i) => changed[i].1 = live(&entry.after),
+                None => {
+                    slot.insert(entry.transaction_id, changed.len());
+                    changed.push((live(&entry.before), live(&entry.after)));
+                }
+            }
+        }
+
+        let mut diff = Diff {
+            from,
+            to,
+            created: Vec::new(),
+            updated: Vec::new(),
+            deleted: Vec::new(),
+        };
+        for (before, after) in changed {
+            match (before, after) {
+                (None, Some(after)) => diff.created.push(after),
+                (Some(before), None) => diff.deleted.push(before),
+                (Some(before), Some(after)) if before != after => {
+                    diff.updated.push(Modified { before, after })
+                }
+                _ => {}
+            }
+        }
+        diff
+    }
+}
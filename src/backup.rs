@@ -0,0 +1,93 @@
+use crate::Transaction;
+use crate::storage::{read_json_file, write_json_file};
+use chrono::Utc;
+use std::io;
+use std::path::PathBuf;
+use tokio::fs;
+
+const PREFIX: &str = "transactions-";
+const SUFFIX: &str = ".json";
+
+/// Point-in-time copies of the ledger, written as JSON whatever the storage
+/// backend so any backup can be inspected or restored the same way.
+pub struct Backups {
+    dir: PathBuf,
+    /// newest backups kept after each write; 0 keeps everything
+    retention: usize,
+}
+
+impl Backups {
+    pub fn new(dir: impl Into<PathBuf>, retention: usize) -> Self {
+        Self {
+            dir: dir.into(),
+            retention,
+        }
+    }
+
+    /// Write `txs` to a new timestamped file and prune old ones. Returns the file name.
+    pub async fn create(&self, txs: &[Transaction]) -> io::Result<String> {
+        fs::create_dir_all(&self.dir).await?;
+        // millisecond precision keeps names unique and lexically ordered by time
+        let name = format!(
+            "{}{}{}",
+            PREFIX,
+            Utc::now().format("%Y%m%dT%H%M%S%.3fZ"),
+            SUFFIX
+        );
+        let path = self.dir.join(&name);
+        write_json_file(&path.to_string_lossy(), txs).await?;
+        self.prune().await?;
+        Ok(name)
+    }
+
+    /// Backup file names, oldest first.
+    pub async fn list(&self) -> io::Result<Vec<String>> {
+        let mut entries = match fs::read_dir(&self.dir).await {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e),
+        };
+        let mut names = Vec::new();
+        while let Some(entry) = entries.next_entry().await? {
+            if let Some(name) = entry.file_name().to_str()
+                && name.starts_with(PREFIX)
+                && name.ends_with(SUFFIX)
+            {
+                names.push(name.to_string());
+            }
+        }
+        names.sort();
+        Ok(names)
+    }
+
+    /// Load and parse a backup by file name. Names outside the backup directory's
+    /// naming scheme are rejected with `InvalidInput`, unparseable files with `InvalidData`.
+    pub async fn read(&self, name: &str) -> io::Result<Vec<Transaction>> {
+        let valid = name.starts_with(PREFIX)
+            && name.ends_with(SUFFIX)
+            && !name.contains(['/', '\\'])
+            && !name.contains("..");
+        if !valid {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "not a backup file name",
+            ));
+        }
+        let path = self.dir.join(name);
+        read_json_file(&path.to_string_lossy())
+            .await?
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "backup not found"))
+    }
+
+    async fn prune(&self) -> io::Result<()> {
+        if self.retention == 0 {
+            return Ok(());
+        }
+        let names = self.list().await?;
+        let excess = names.len().saturating_sub(self.retention);
+        for name in &names[..excess] {
+            fs::remove_file(self.dir.join(name)).await?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,126 @@
+use crate::statement::StatementPeriod;
+use crate::storage::{read_json_file, write_json_file};
+use crate::{
+    CURRENCY_ERROR, FieldError, Transaction, default_currency, is_valid_currency, round_amount,
+};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::io;
+use tokio::sync::RwLock;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+/// A monthly spending limit for one user's category, in one currency.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct Budget {
+    pub id: Uuid,
+    pub user: String,
+    pub category: String,
+    pub currency: String,
+    /// most the category may add up to in a calendar month
+    pub limit: Decimal,
+}
+
+/// Body for POST and PUT /budgets.
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct CreateBudget {
+    pub user: String,
+    pub category: String,
+    #[serde(default = "default_currency")]
+    pub currency: String,
+    pub limit: Decimal,
+}
+
+impl CreateBudget {
+    pub fn validate(&self) -> Result<(), Vec<FieldError>> {
+        let mut errors = Vec::new();
+        if self.user.trim().is_empty() {
+            errors.push(FieldError::new("user", "required"));
+        }
+        if self.category.trim().is_empty() {
+            errors.push(FieldError::new("category", "required"));
+        }
+        if !is_valid_currency(&self.currency) {
+            errors.push(FieldError::new("currency", CURRENCY_ERROR));
+        }
+        if self.limit.is_sign_negative() {
+            errors.push(FieldError::new("limit", "must be a non-negative number"));
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Build a budget with a fresh id; call validate() first.
+    pub fn into_budget(self) -> Budget {
+        Budget {
+            id: Uuid::new_v4(),
+            user: self.user.trim().to_string(),
+            category: self.category.trim().to_string(),
+            limit: round_amount(self.limit, &self.currency),
+            currency: self.currency,
+        }
+    }
+}
+
+/// How a budget stands for one month, as shown in the user summary.
+#[derive(Debug, Serialize)]
+pub struct BudgetStatus<'a> {
+    pub category: &'a str,
+    pub currency: &'a str,
+    pub limit: Decimal,
+    pub spent: Decimal,
+    pub over_budget: bool,
+}
+
+impl Budget {
+    /// Net amount booked against this budget in `period`; credits count against
+    /// spending. `txs` should already be limited to live rows.
+    pub fn status<'a>(
+        &'a self,
+        period: &StatementPeriod,
+        txs: &[&Transaction],
+    ) -> BudgetStatus<'a> {
+        let spent: Decimal = txs
+            .iter()
+            .filter(|t| {
+                t.user == self.user
+                    && t.category.as_deref() == Some(self.category.as_str())
+                    && t.currency == self.currency
+                    && period.contains(t.timestamp)
+            })
+            .map(|t| t.amount)
+            .sum();
+        let spent = round_amount(spent, &self.currency);
+        BudgetStatus {
+            category: &self.category,
+            currency: &self.currency,
+            limit: self.limit,
+            spent,
+            over_budget: spent > self.limit,
+        }
+    }
+}
+
+/// Budgets, kept in their own JSON file next to the ledger.
+pub struct BudgetStore {
+    file_path: String,
+    pub budgets: RwLock<Vec<Budget>>,
+}
+
+impl BudgetStore {
+    pub async fn load(file_path: impl Into<String>) -> io::Result<Self> {
+        let file_path = file_path.into();
+        let budgets = read_json_file(&file_path).await?.unwrap_or_default();
+        Ok(Self {
+            file_path,
+            budgets: RwLock::new(budgets),
+        })
+    }
+
+    pub async fn save(&self, budgets: &[Budget]) -> io::Result<()> {
+        write_json_file(&self.file_path, budgets).await
+    }
+}
@@ -0,0 +1,452 @@
+use crate::format::Locale;
+use std::fmt;
+use std::io;
+use std::path::Path;
+use std::str::FromStr;
+use std::time::Duration;
+
+const STORAGE_FILE: &str = "transactions.json";
+const SQLITE_DB_FILE: &str = "bookkeeping.db";
+const RECURRING_FILE: &str = "recurring.json";
+const BUDGETS_FILE: &str = "budgets.json";
+const AUDIT_FILE: &str = "audit.jsonl";
+const ARCHIVE_FILE: &str = "archive.json";
+const WAL_FILE: &str = "transactions.wal";
+const BACKUP_DIR: &str = "backups";
+const RATES_FILE: &str = "rates.json";
+const RECEIPTS_DIR: &str = "receipts";
+/// largest receipt upload accepted
+const DEFAULT_MAX_RECEIPT_BYTES: usize = 10 * 1024 * 1024;
+/// newest backups kept; 0 keeps all of them
+const DEFAULT_BACKUP_RETENTION: usize = 10;
+/// how often due recurring templates are turned into transactions
+const DEFAULT_RECURRING_CHECK_SECS: u64 = 60;
+const DEFAULT_HOST: &str = "127.0.0.1";
+const DEFAULT_PORT: u16 = 3000;
+/// 0 disables debouncing and writes through on every mutation
+const DEFAULT_FLUSH_INTERVAL_MS: u64 = 500;
+/// applies to JSON bodies and CSV imports alike
+const DEFAULT_MAX_BODY_BYTES: usize = 1024 * 1024;
+/// how long a request waits for the transaction lock; 0 waits forever
+const DEFAULT_LOCK_TIMEOUT_MS: u64 = 5000;
+/// per client IP; 0 disables rate limiting
+const DEFAULT_RATE_LIMIT_PER_MINUTE: u32 = 60;
+/// how long a retried Idempotency-Key returns the original transaction
+const DEFAULT_IDEMPOTENCY_TTL_SECS: u64 = 24 * 60 * 60;
+/// POST /transactions rejects a same user/item/amount entry this many seconds apart
+const DEFAULT_DUPLICATE_WINDOW_SECS: u64 = 60;
+/// mutations /admin/undo can step back through
+const DEFAULT_UNDO_HISTORY: usize = 50;
+
+/// Deployment profile picked with `BOOKKEEPING_ENV`: `dev` (the default when
+/// unset) or `prod`, also accepted spelled out; anything else fails startup.
+/// It only changes defaults; every setting it touches can still be set
+/// explicitly.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Profile {
+    /// pretty storage JSON, any CORS origin, debug logging, API key optional
+    #[default]
+    Dev,
+    /// compact storage JSON, no CORS origins unless listed, info logging,
+    /// and startup fails without an API key
+    Prod,
+}
+
+impl FromStr for Profile {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, ()> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "dev" | "development" => Ok(Profile::Dev),
+            "prod" | "production" => Ok(Profile::Prod),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Where transactions are kept, picked with `--storage json|sqlite`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StorageBackend {
+    Json,
+    /// path from `--db`
+    Sqlite {
+        db_path: String,
+    },
+}
+
+/// A value that must never reach the logs; `Debug` prints a placeholder.
+#[derive(Clone)]
+pub struct Secret(String);
+
+impl Secret {
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("[redacted]")
+    }
+}
+
+/// Every setting, read once at startup from `BOOKKEEPING_*` env vars and the
+/// command line. Its `Debug` output is safe to log.
+#[derive(Debug)]
+pub struct Config {
+    pub profile: Profile,
+    /// used when RUST_LOG is unset
+    pub log_filter: &'static str,
+    pub host: String,
+    pub port: u16,
+    /// prefix every route is served under, e.g. `/api/v1`; empty serves from the root
+    pub base_path: String,
+
+    pub storage: StorageBackend,
+    /// the JSON backend's file
+    pub storage_file: String,
+    /// indent the storage file
+    pub pretty_json: bool,
+    /// move an unparseable storage file aside instead of refusing to start
+    pub quarantine_corrupt: bool,
+    /// refuse to start when stored transactions reuse an id
+    pub strict_ids: bool,
+    pub wal_file: String,
+    /// None means write-through: every mutation hits storage immediately
+    pub flush_interval: Option<Duration>,
+
+    pub recurring_file: String,
+    pub budgets_file: String,
+    pub audit_file: String,
+    pub archive_file: String,
+    pub rates_file: String,
+    pub backup_dir: String,
+    pub receipts_dir: String,
+
+    /// 0 keeps everything in the live ledger
+    pub archive_after_days: u64,
+    /// 0 turns off the recurring job
+    pub recurring_check_secs: u64,
+    /// seconds between automatic backups; 0 leaves them manual
+    pub backup_interval_secs: u64,
+    pub backup_retention: usize,
+
+    /// request body cap for endpoints that read raw payloads
+    pub max_body_bytes: usize,
+    pub max_receipt_bytes: usize,
+    /// requests still waiting for the transaction lock after this get a 503;
+    /// `None` waits forever
+    pub lock_timeout: Option<Duration>,
+    /// `None` leaves the API open
+    pub api_key: Option<Secret>,
+    /// 0 disables rate limiting
+    pub rate_limit_per_minute: u32,
+    /// `None` allows any origin
+    pub cors_origins: Option<Vec<String>>,
+    /// refuse every change, for demos and migrations
+    pub readonly: bool,
+
+    pub idempotency_ttl: Duration,
+    /// creates matching an existing entry this close in time get a 409; 0 disables
+    pub duplicate_window_secs: u64,
+    pub undo_history: usize,
+    /// group item names by fold_item() in reports and /items
+    pub fold_item_names: bool,
+    /// how amounts are written in the HTML statement
+    pub locale: Locale,
+    /// for new entries that don't name a currency; `None` keeps the built-in USD
+    pub default_currency: Option<String>,
+}
+
+impl Config {
+    /// The file transactions are stored in, whichever backend is in use.
+    pub fn storage_path(&self) -> &str {
+        match &self.storage {
+            StorageBackend::Json => &self.storage_file,
+            StorageBackend::Sqlite { db_path } => db_path,
+        }
+    }
+
+    /// Read the environment and `args` (without the program name), then
+    /// validate the result.
+    pub fn load(args: impl Iterator<Item = String>) -> io::Result<Self> {
+        let profile: Profile = env_parse("BOOKKEEPING_ENV", Profile::default())?;
+        let dev = profile == Profile::Dev;
+
+        // comma-separated origins, or "*" for any
+        let cors_origins = match std::env::var("BOOKKEEPING_CORS_ORIGINS") {
+            Ok(v) if v.trim() == "*" => None,
+            Ok(v) => Some(
+                v.split(',')
+                    .map(str::trim)
+                    .filter(|o| !o.is_empty())
+                    .map(str::to_string)
+                    .collect(),
+            ),
+            Err(_) if dev => None,
+            Err(_) => Some(Vec::new()),
+        };
+        let flush_interval_ms: u64 =
+            env_parse("BOOKKEEPING_FLUSH_INTERVAL_MS", DEFAULT_FLUSH_INTERVAL_MS)?;
+        let lock_timeout_ms: u64 =
+            env_parse("BOOKKEEPING_LOCK_TIMEOUT_MS", DEFAULT_LOCK_TIMEOUT_MS)?;
+        let idempotency_ttl_secs: u64 = env_parse(
+            "BOOKKEEPING_IDEMPOTENCY_TTL_SECS",
+            DEFAULT_IDEMPOTENCY_TTL_SECS,
+        )?;
+
+        let config = Self {
+            profile,
+            log_filter: if dev { "debug" } else { "info" },
+            host: env_string("BOOKKEEPING_HOST", DEFAULT_HOST),
+            port: env_parse("BOOKKEEPING_PORT", DEFAULT_PORT)?,
+            base_path: normalize_base_path(&env_string("BOOKKEEPING_BASE_PATH", "")),
+
+            storage: storage_from_args(args)?,
+            storage_file: env_string("BOOKKEEPING_STORAGE_FILE", STORAGE_FILE),
+            pretty_json: env_parse("BOOKKEEPING_PRETTY_JSON", dev)?,
+            quarantine_corrupt: env_parse("BOOKKEEPING_QUARANTINE_CORRUPT", false)?,
+            strict_ids: env_parse("BOOKKEEPING_STRICT_IDS", false)?,
+            wal_file: env_string("BOOKKEEPING_WAL_FILE", WAL_FILE),
+            flush_interval: (flush_interval_ms > 0)
+                .then(|| Duration::from_millis(flush_interval_ms)),
+
+            recurring_file: env_string("BOOKKEEPING_RECURRING_FILE", RECURRING_FILE),
+            budgets_file: env_string("BOOKKEEPING_BUDGETS_FILE", BUDGETS_FILE),
+            audit_file: env_string("BOOKKEEPING_AUDIT_FILE", AUDIT_FILE),
+            archive_file: env_string("BOOKKEEPING_ARCHIVE_FILE", ARCHIVE_FILE),
+            rates_file: env_string("BOOKKEEPING_RATES_FILE", RATES_FILE),
+            backup_dir: env_string("BOOKKEEPING_BACKUP_DIR", BACKUP_DIR),
+            receipts_dir: env_string("BOOKKEEPING_RECEIPTS_DIR", RECEIPTS_DIR),
+
+            archive_after_days: env_parse("BOOKKEEPING_ARCHIVE_OLDER_THAN_DAYS", 0)?,
+            recurring_check_secs: env_parse(
+                "BOOKKEEPING_RECURRING_CHECK_SECS",
+                DEFAULT_RECURRING_CHECK_SECS,
+            )?,
+            backup_interval_secs: env_parse("BOOKKEEPING_BACKUP_INTERVAL", 0)?,
+            backup_retention: env_parse("BOOKKEEPING_BACKUP_RETENTION", DEFAULT_BACKUP_RETENTION)?,
+
+            max_body_bytes: env_parse("BOOKKEEPING_MAX_BODY_BYTES", DEFAULT_MAX_BODY_BYTES)?,
+            max_receipt_bytes: env_parse(
+                "BOOKKEEPING_MAX_RECEIPT_BYTES",
+                DEFAULT_MAX_RECEIPT_BYTES,
+            )?,
+            lock_timeout: (lock_timeout_ms > 0).then(|| Duration::from_millis(lock_timeout_ms)),
+            // an empty key counts as unset
+            api_key: std::env::var("BOOKKEEPING_API_KEY")
+                .ok()
+                .filter(|k| !k.is_empty())
+                .map(Secret),
+            rate_limit_per_minute: env_parse(
+                "BOOKKEEPING_RATE_LIMIT_PER_MINUTE",
+                DEFAULT_RATE_LIMIT_PER_MINUTE,
+            )?,
+            cors_origins,
+            readonly: env_parse("BOOKKEEPING_READONLY", false)?,
+
+            idempotency_ttl: Duration::from_secs(idempotency_ttl_secs),
+            duplicate_window_secs: env_parse(
+                "BOOKKEEPING_DUPLICATE_WINDOW_SECS",
+                DEFAULT_DUPLICATE_WINDOW_SECS,
+            )?,
+            undo_history: env_parse("BOOKKEEPING_UNDO_HISTORY", DEFAULT_UNDO_HISTORY)?,
+            fold_item_names: env_parse("BOOKKEEPING_FOLD_ITEM_NAMES", true)?,
+            locale: env_parse("BOOKKEEPING_LOCALE", Locale::default())?,
+            default_currency: std::env::var("BOOKKEEPING_DEFAULT_CURRENCY")
+                .ok()
+                .map(|c| c.trim().to_string()),
+        };
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Check settings that parse but can't work, reporting all of them at once.
+    fn validate(&self) -> io::Result<()> {
+        let mut problems = Vec::new();
+        if self.api_key.is_none() && self.profile == Profile::Prod {
+            problems.push(
+                "BOOKKEEPING_API_KEY must be set in the prod profile \
+                 (set BOOKKEEPING_ENV=dev to run without authentication)"
+                    .to_string(),
+            );
+        }
+        if self.host.trim().is_empty() {
+            problems.push("BOOKKEEPING_HOST must not be empty".to_string());
+        }
+        if self
+            .base_path
+            .chars()
+            .any(|c| c.is_whitespace() || matches!(c, '{' | '}' | '?' | '#'))
+        {
+            problems.push(
+                "BOOKKEEPING_BASE_PATH must be a plain path without spaces, braces, ? or #"
+                    .to_string(),
+            );
+        }
+        if self.max_body_bytes == 0 {
+            problems.push("BOOKKEEPING_MAX_BODY_BYTES must be greater than 0".to_string());
+        }
+        if self.max_receipt_bytes == 0 {
+            problems.push("BOOKKEEPING_MAX_RECEIPT_BYTES must be greater than 0".to_string());
+        }
+        if let Some(currency) = &self.default_currency
+            && !crate::is_valid_currency(currency)
+        {
+            problems.push(format!(
+                "BOOKKEEPING_DEFAULT_CURRENCY {}",
+                crate::CURRENCY_ERROR
+            ));
+        }
+        // each file is rewritten wholesale by its owner, so two sharing a path clobber each other
+        let mut files = vec![
+            ("BOOKKEEPING_WAL_FILE", &self.wal_file),
+            ("BOOKKEEPING_RECURRING_FILE", &self.recurring_file),
+            ("BOOKKEEPING_BUDGETS_FILE", &self.budgets_file),
+            ("BOOKKEEPING_AUDIT_FILE", &self.audit_file),
+            ("BOOKKEEPING_ARCHIVE_FILE", &self.archive_file),
+            ("BOOKKEEPING_RATES_FILE", &self.rates_file),
+        ];
+        match &self.storage {
+            StorageBackend::Json => files.push(("BOOKKEEPING_STORAGE_FILE", &self.storage_file)),
+            StorageBackend::Sqlite { db_path } => files.push(("--db", db_path)),
+        }
+        for (i, (name, path)) in files.iter().enumerate() {
+            if path.trim().is_empty() {
+                problems.push(format!("{} must not be empty", name));
+            } else if let Some((other, _)) = files[..i].iter().find(|(_, p)| p == path) {
+                problems.push(format!("{} and {} both point at {}", other, name, path));
+            }
+        }
+        // backups are pruned by listing their folder and a new receipt clears out its
+        // transaction's subfolder, so the two must stay apart and hold none of the files above
+        let dirs = [
+            ("BOOKKEEPING_BACKUP_DIR", &self.backup_dir),
+            ("BOOKKEEPING_RECEIPTS_DIR", &self.receipts_dir),
+        ];
+        for (i, (name, dir)) in dirs.iter().enumerate() {
+            if dir.trim().is_empty() {
+                problems.push(format!("{} must not be empty", name));
+                continue;
+            }
+            let within = |path: &str| Path::new(path).starts_with(dir.as_str());
+            if let Some((other, other_dir)) = dirs[..i]
+                .iter()
+                .find(|(_, d)| within(d) || Path::new(dir.as_str()).starts_with(d.as_str()))
+            {
+                problems.push(format!(
+                    "{} and {} overlap ({} and {})",
+                    other, name, other_dir, dir
+                ));
+            }
+            for (file, path) in files.iter().filter(|(_, p)| within(p)) {
+                problems.push(format!("{} ({}) is inside {} ({})", file, path, name, dir));
+            }
+        }
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("invalid configuration: {}", problems.join("; ")),
+            ))
+        }
+    }
+}
+
+/// Read and parse an env var, falling back to `default` when it is unset.
+fn env_parse<T: FromStr>(name: &str, default: T) -> io::Result<T> {
+    match std::env::var(name) {
+        Ok(raw) => raw.trim().parse::<T>().map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} has an invalid value: {:?}", name, raw),
+            )
+        }),
+        Err(_) => Ok(default),
+    }
+}
+
+/// `api/v1/` and `/api/v1` both become `/api/v1`; `/` becomes empty.
+fn normalize_base_path(raw: &str) -> String {
+    let trimmed = raw.trim().trim_matches('/');
+    if trimmed.is_empty() {
+        String::new()
+    } else {
+        format!("/{}", trimmed)
+    }
+}
+
+fn env_string(name: &str, default: &str) -> String {
+    std::env::var(name).unwrap_or_else(|_| default.to_string())
+}
+
+/// Parse `--storage json|sqlite` and `--db <path>`.
+fn storage_from_args(mut args: impl Iterator<Item = String>) -> io::Result<StorageBackend> {
+    let invalid = |msg: String| io::Error::new(io::ErrorKind::InvalidInput, msg);
+    let mut kind = "json".to_string();
+    let mut db_path = SQLITE_DB_FILE.to_string();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--storage" => {
+                kind = args
+                    .next()
+                    .ok_or_else(|| invalid("--storage requires a value".to_string()))?
+            }
+            "--db" => {
+                db_path = args
+                    .next()
+                    .ok_or_else(|| invalid("--db requires a value".to_string()))?
+            }
+            other => return Err(invalid(format!("unknown argument: {}", other))),
+        }
+    }
+
+    match kind.as_str() {
+        "json" => Ok(StorageBackend::Json),
+        "sqlite" => Ok(StorageBackend::Sqlite { db_path }),
+        other => Err(invalid(format!(
+            "unknown storage backend: {} (expected json or sqlite)",
+            other
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> Config {
+        let mut config = Config::load(std::iter::empty()).unwrap();
+        config.storage = StorageBackend::Json;
+        config.storage_file = "data/transactions.json".to_string();
+        config.backup_dir = "backups".to_string();
+        config.receipts_dir = "receipts".to_string();
+        config
+    }
+
+    #[test]
+    fn receipts_may_not_share_the_backup_dir() {
+        assert!(config().validate().is_ok());
+        let mut same = config();
+        same.receipts_dir = same.backup_dir.clone();
+        let err = same.validate().unwrap_err().to_string();
+        assert!(err.contains("BOOKKEEPING_RECEIPTS_DIR"), "{}", err);
+        let mut nested = config();
+        nested.receipts_dir = "backups/receipts".to_string();
+        assert!(nested.validate().is_err());
+    }
+
+    #[test]
+    fn data_files_may_not_live_in_the_backup_or_receipts_dir() {
+        let mut backups = config();
+        backups.backup_dir = "data".to_string();
+        let err = backups.validate().unwrap_err().to_string();
+        assert!(err.contains("BOOKKEEPING_STORAGE_FILE"), "{}", err);
+        let mut receipts = config();
+        receipts.receipts_dir = "data".to_string();
+        let err = receipts.validate().unwrap_err().to_string();
+        assert!(err.contains("BOOKKEEPING_RECEIPTS_DIR"), "{}", err);
+    }
+}
@@ -0,0 +1,55 @@
+use actix_web::http::header;
+use actix_web::{HttpRequest, web};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+/// Bumped whenever a response shape changes incompatibly.
+pub const API_VERSION: &str = "1";
+/// `Accept` value that opts a request into the envelope.
+pub const ENVELOPE_MEDIA_TYPE: &str = "application/vnd.bookkeeping.v1+json";
+
+/// `{ data, meta }` wrapper for successful JSON responses. Opt-in for now, so
+/// clients reading the raw bodies keep working through the transition.
+#[derive(Serialize)]
+pub struct ApiResponse<T> {
+    pub data: T,
+    pub meta: ApiMeta,
+}
+
+impl<T> ApiResponse<T> {
+    pub fn new(data: T) -> Self {
+        Self {
+            data,
+            meta: ApiMeta {
+                api_version: API_VERSION,
+            },
+        }
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ApiMeta {
+    pub api_version: &'static str,
+}
+
+#[derive(Deserialize)]
+struct EnvelopeQuery {
+    envelope: Option<bool>,
+}
+
+/// Whether the client asked for the envelope, via `Accept` or `?envelope=true`.
+pub fn requested(req: &HttpRequest) -> bool {
+    let by_accept = req
+        .headers()
+        .get_all(header::ACCEPT)
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .any(|v| {
+            v.split(';')
+                .next()
+                .is_some_and(|media| media.trim().eq_ignore_ascii_case(ENVELOPE_MEDIA_TYPE))
+        });
+    by_accept
+        || web::Query::<EnvelopeQuery>::from_query(req.query_string())
+            .is_ok_and(|q| q.envelope == Some(true))
+}
@@ -0,0 +1,142 @@
+use crate::Transaction;
+use actix_web::web::Bytes;
+use futures_util::Stream;
+use serde::{Deserialize, Serialize};
+use std::convert::Infallible;
+use std::time::Duration;
+use tokio::sync::broadcast;
+use utoipa::ToSchema;
+
+/// Events buffered per subscriber before a slow one starts missing them.
+const CHANNEL_CAPACITY: usize = 256;
+/// Idle SSE connections get a comment this often so dead clients are noticed.
+const SSE_KEEP_ALIVE: Duration = Duration::from_secs(15);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ChangeKind {
+    Created,
+    Updated,
+    Deleted,
+    Restored,
+    /// dropped outright by undoing its creation, rather than soft-deleted
+    Removed,
+}
+
+impl ChangeKind {
+    fn as_str(self) -> &'static str {
+        match self {
+            ChangeKind::Created => "created",
+            ChangeKind::Updated => "updated",
+            ChangeKind::Deleted => "deleted",
+            ChangeKind::Restored => "restored",
+            ChangeKind::Removed => "removed",
+        }
+    }
+}
+
+/// One mutation, as pushed to live subscribers.
+#[derive(Debug, Clone, Serialize)]
+pub struct ChangeEvent {
+    #[serde(rename = "type")]
+    pub kind: ChangeKind,
+    pub transaction: Transaction,
+}
+
+/// Fan-out of ledger changes to every connected live client.
+pub struct ChangeFeed {
+    sender: broadcast::Sender<ChangeEvent>,
+}
+
+impl ChangeFeed {
+    pub fn new() -> Self {
+        let (sender, _) = broadcast::channel(CHANNEL_CAPACITY);
+        Self { sender }
+    }
+
+    /// Publish after the change is stored; with nobody listening this is a no-op.
+    pub fn publish(&self, kind: ChangeKind, transaction: &Transaction) {
+        if self.sender.receiver_count() > 0 {
+            let _ = self.sender.send(ChangeEvent {
+                kind,
+                transaction: transaction.clone(),
+            });
+        }
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<ChangeEvent> {
+        self.sender.subscribe()
+    }
+}
+
+/// Relay events to one WebSocket client until either side goes away.
+pub async fn forward_to_socket(
+    mut session: actix_ws::Session,
+    mut incoming: actix_ws::MessageStream,
+    mut events: broadcast::Receiver<ChangeEvent>,
+) {
+    loop {
+        tokio::select! {
+            event = events.recv() => {
+                let text = match event {
+                    Ok(event) => match serde_json::to_string(&event) {
+                        Ok(text) => text,
+                        Err(e) => {
+                            tracing::error!(error = %e, "Failed to serialize change event");
+                            continue;
+                        }
+                    },
+                    // a slow client gets told how much it missed rather than being dropped
+                    Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                        serde_json::json!({"type": "lagged", "skipped": skipped}).to_string()
+                    }
+                    Err(broadcast::error::RecvError::Closed) => break,
+                };
+                if session.text(text).await.is_err() {
+                    return;
+                }
+            }
+            message = incoming.recv() => match message {
+                Some(Ok(actix_ws::Message::Ping(bytes))) => {
+                    if session.pong(&bytes).await.is_err() {
+                        return;
+                    }
+                }
+                Some(Ok(actix_ws::Message::Close(reason))) => {
+                    let _ = session.close(reason).await;
+                    return;
+                }
+                // the feed is one-way; anything else the client sends is ignored
+                Some(Ok(_)) => {}
+                Some(Err(_)) | None => break,
+            },
+        }
+    }
+    let _ = session.close(None).await;
+}
+
+/// Server-Sent Events body: one `event: <kind>` frame per change, with the
+/// transaction as its data. The stream (and with it the subscription) is
+/// dropped when the client disconnects.
+pub fn sse_stream(
+    events: broadcast::Receiver<ChangeEvent>,
+) -> impl Stream<Item = Result<Bytes, Infallible>> {
+    futures_util::stream::unfold(events, |mut events| async move {
+        let frame = match tokio::time::timeout(SSE_KEEP_ALIVE, events.recv()).await {
+            Ok(Ok(event)) => match serde_json::to_string(&event.transaction) {
+                Ok(data) => format!("event: {}\ndata: {}\n\n", event.kind.as_str(), data),
+                Err(e) => {
+                    tracing::error!(error = %e, "Failed to serialize change event");
+                    ": dropped an event that failed to serialize\n\n".to_string()
+                }
+            },
+            Ok(Err(broadcast::error::RecvError::Lagged(skipped))) => {
+                format!("event: lagged\ndata: {{\"skipped\":{}}}\n\n", skipped)
+            }
+            Ok(Err(broadcast::error::RecvError::Closed)) => return None,
+            // writing something is the only way to find out the client has gone
+            Err(_) => ": keep-alive\n\n".to_string(),
+        };
+        Some((Ok(Bytes::from(frame)), events))
+    })
+}
@@ -0,0 +1,323 @@
+use rust_decimal::Decimal;
+use rust_decimal::prelude::ToPrimitive;
+use std::str::FromStr;
+
+/// How amounts are written in human-readable output such as the HTML
+/// statement. The JSON API always returns plain numbers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Locale {
+    /// `$1,234.56`
+    #[default]
+    EnUs,
+    /// `£1,234.56`
+    EnGb,
+    /// `1.234,56 €`
+    DeDe,
+    /// `1 234,56 €`, grouped with a narrow no-break space
+    FrFr,
+}
+
+impl FromStr for Locale {
+    type Err = ();
+
+    /// Accepts `en-US`, `en_US` and the like, in any case.
+    fn from_str(s: &str) -> Result<Self, ()> {
+        match s.trim().replace('_', "-").to_ascii_lowercase().as_str() {
+            "en-us" | "en" => Ok(Locale::EnUs),
+            "en-gb" => Ok(Locale::EnGb),
+            "de-de" | "de" => Ok(Locale::DeDe),
+            "fr-fr" | "fr" => Ok(Locale::FrFr),
+            _ => Err(()),
+        }
+    }
+}
+
+fn currency_symbol(code: &str) -> Option<&'static str> {
+    Some(match code {
+        "USD" => "$",
+        "EUR" => "€",
+        "GBP" => "£",
+        "JPY" => "¥",
+        "INR" => "₹",
+        _ => return None,
+    })
+}
+
+impl Locale {
+    /// (grouping separator, decimal separator)
+    fn separators(self) -> (&'static str, char) {
+        match self {
+            Locale::EnUs | Locale::EnGb => (",", '.'),
+            Locale::DeDe => (".", ','),
+            Locale::FrFr => ("\u{202f}", ','),
+        }
+    }
+
+    /// Whether the symbol goes before the number.
+    fn symbol_first(self) -> bool {
+        matches!(self, Locale::EnUs | Locale::EnGb)
+    }
+
+    /// `amount` rounded to the currency's minor unit, with thousands grouping
+    /// and the currency symbol (or code, for currencies without one) placed
+    /// the way this locale writes it.
+    pub fn format_money(self, amount: Decimal, currency: &str) -> String {
+        let decimals = crate::currency_decimals(currency);
+        let rounded = crate::round_amount(amount, currency).abs();
+        let digits = format!("{:.*}", decimals as usize, rounded);
+        let (whole, fraction) = match digits.split_once('.') {
+            Some((whole, fraction)) => (whole, Some(fraction)),
+            None => (digits.as_str(), None),
+        };
+
+        let (group, decimal) = self.separators();
+        let mut number = String::new();
+        for (i, c) in whole.chars().enumerate() {
+            if i > 0 && (whole.len() - i) % 3 == 0 {
+                number.push_str(group);
+            }
+            number.push(c);
+        }
+        if let Some(fraction) = fraction {
+            number.push(decimal);
+            number.push_str(fraction);
+        }
+
+        let sign = if amount.is_sign_negative() && !rounded.is_zero() {
+            "-"
+        } else {
+            ""
+        };
+        match (currency_symbol(currency), self.symbol_first()) {
+            (Some(symbol), true) => format!("{}{}{}", sign, symbol, number),
+            (None, true) => format!("{}{} {}", sign, currency, number),
+            (Some(symbol), false) => format!("{}{} {}", sign, number, symbol),
+            (None, false) => format!("{}{} {}", sign, number, currency),
+        }
+    }
+}
+
+const ONES: [&str; 20] = [
+    "zero",
+    "one",
+    "two",
+    "three",
+    "four",
+    "five",
+    "six",
+    "seven",
+    "eight",
+    "nine",
+    "ten",
+    "eleven",
+    "twelve",
+    "thirteen",
+    "fourteen",
+    "fifteen",
+    "sixteen",
+    "seventeen",
+    "eighteen",
+    "nineteen",
+];
+const TENS: [&str; 10] = [
+    "", "", "twenty", "thirty", "forty", "fifty", "sixty", "seventy", "eighty", "ninety",
+];
+/// one per group of three digits; enough for any Decimal
+const SCALES: [&str; 10] = [
+    "",
+    "thousand",
+    "million",
+    "billion",
+    "trillion",
+    "quadrillion",
+    "quintillion",
+    "sextillion",
+    "septillion",
+    "octillion",
+];
+
+/// (singular, plural)
+type UnitName = (&'static str, &'static str);
+
+/// Names of the major and minor unit.
+fn currency_units(code: &str) -> Option<(UnitName, UnitName)> {
+    Some(match code {
+        "USD" | "CAD" | "AUD" | "NZD" | "SGD" | "HKD" => (("dollar", "dollars"), ("cent", "cents")),
+        "EUR" => (("euro", "euros"), ("cent", "cents")),
+        "GBP" => (("pound", "pounds"), ("penny", "pence")),
+        "JPY" => (("yen", "yen"), ("sen", "sen")),
+        "INR" => (("rupee", "rupees"), ("paisa", "paise")),
+        "CHF" => (("franc", "francs"), ("centime", "centimes")),
+        _ => return None,
+    })
+}
+
+/// 0..=999
+fn hundreds_in_words(n: u32, words: &mut Vec<String>) {
+    if n >= 100 {
+        words.push(format!("{} hundred", ONES[(n / 100) as usize]));
+    }
+    match n % 100 {
+        0 => {}
+        r if r < 20 => words.push(ONES[r as usize].to_string()),
+        r if r % 10 == 0 => words.push(TENS[(r / 10) as usize].to_string()),
+        r => words.push(format!(
+            "{}-{}",
+            TENS[(r / 10) as usize],
+            ONES[(r % 10) as usize]
+        )),
+    }
+}
+
+/// `123` as `one hundred twenty-three`.
+pub fn number_in_words(mut n: u128) -> String {
+    if n == 0 {
+        return ONES[0].to_string();
+    }
+    let mut groups = Vec::new();
+    while n > 0 {
+        groups.push((n % 1000) as u32);
+        n /= 1000;
+    }
+    let mut words = Vec::new();
+    for (scale, group) in groups.into_iter().enumerate().rev() {
+        if group == 0 {
+            continue;
+        }
+        hundreds_in_words(group, &mut words);
+        if scale > 0 {
+            words.push(SCALES[scale].to_string());
+        }
+    }
+    words.join(" ")
+}
+
+/// `amount` spelled out as it would be on a cheque, e.g. `one hundred
+/// twenty-three dollars and forty-five cents`. Rounded to the currency's
+/// minor unit first; negative amounts start with `minus`. Currencies without
+/// known unit names use the code and write the minor part as a fraction
+/// (`twelve SEK and 50/100`).
+pub fn amount_in_words(amount: Decimal, currency: &str) -> String {
+    let decimals = crate::currency_decimals(currency);
+    let rounded = crate::round_amount(amount, currency);
+    let magnitude = rounded.abs();
+    let minor_per_major = 10u128.pow(decimals);
+    let whole = magnitude.trunc().to_u128().unwrap_or_default();
+    let minor = (magnitude.fract() * Decimal::from(minor_per_major))
+        .to_u128()
+        .unwrap_or_default();
+
+    let units = currency_units(currency);
+    let mut words = number_in_words(whole);
+    words.push(' ');
+    words.push_str(match units {
+        Some(((one, _), _)) if whole == 1 => one,
+        Some(((_, many), _)) => many,
+        None => currency,
+    });
+    if minor > 0 {
+        words.push_str(" and ");
+        match units {
+            Some((_, (one, many))) => {
+                words.push_str(&number_in_words(minor));
+                words.push(' ');
+                words.push_str(if minor == 1 { one } else { many });
+            }
+            None => {
+                words.push_str(&format!(
+                    "{:0width$}/{}",
+                    minor,
+                    minor_per_major,
+                    width = decimals as usize
+                ));
+            }
+        }
+    }
+    if rounded.is_sign_negative() && !rounded.is_zero() {
+        words.insert_str(0, "minus ");
+    }
+    words
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn small_numbers_and_teens() {
+        assert_eq!(number_in_words(0), "zero");
+        assert_eq!(number_in_words(7), "seven");
+        assert_eq!(number_in_words(13), "thirteen");
+        assert_eq!(number_in_words(19), "nineteen");
+        assert_eq!(number_in_words(20), "twenty");
+        assert_eq!(number_in_words(42), "forty-two");
+    }
+
+    #[test]
+    fn hundreds_and_scale_boundaries() {
+        assert_eq!(number_in_words(100), "one hundred");
+        assert_eq!(number_in_words(101), "one hundred one");
+        assert_eq!(number_in_words(999), "nine hundred ninety-nine");
+        assert_eq!(number_in_words(1000), "one thousand");
+        assert_eq!(number_in_words(1001), "one thousand one");
+        assert_eq!(
+            number_in_words(999_999),
+            "nine hundred ninety-nine thousand nine hundred ninety-nine"
+        );
+        assert_eq!(number_in_words(1_000_000), "one million");
+        assert_eq!(number_in_words(1_000_001), "one million one");
+        assert_eq!(
+            number_in_words(2_000_030_000),
+            "two billion thirty thousand"
+        );
+    }
+
+    #[test]
+    fn largest_decimal_is_spelled_out() {
+        let words = amount_in_words(Decimal::MAX, "JPY");
+        assert!(words.starts_with("seventy-nine octillion "), "{}", words);
+        assert!(words.ends_with(" yen"), "{}", words);
+        assert!(!words.contains("  "));
+    }
+
+    #[test]
+    fn minor_units() {
+        let amount = |s: &str| Decimal::from_str(s).unwrap();
+        assert_eq!(
+            amount_in_words(amount("123.45"), "USD"),
+            "one hundred twenty-three dollars and forty-five cents"
+        );
+        assert_eq!(
+            amount_in_words(amount("1.01"), "USD"),
+            "one dollar and one cent"
+        );
+        assert_eq!(
+            amount_in_words(amount("0.99"), "GBP"),
+            "zero pounds and ninety-nine pence"
+        );
+        assert_eq!(amount_in_words(amount("5"), "EUR"), "five euros");
+        // rounded to the minor unit before spelling
+        assert_eq!(
+            amount_in_words(amount("2.005"), "USD"),
+            "two dollars and one cent"
+        );
+        assert_eq!(
+            amount_in_words(amount("21.02"), "SEK"),
+            "twenty-one SEK and 02/100"
+        );
+        assert_eq!(
+            amount_in_words(amount("1.500"), "KWD"),
+            "one KWD and 500/1000"
+        );
+    }
+
+    #[test]
+    fn negatives_say_minus_but_zero_does_not() {
+        let amount = |s: &str| Decimal::from_str(s).unwrap();
+        assert_eq!(
+            amount_in_words(amount("-3.5"), "USD"),
+            "minus three dollars and fifty cents"
+        );
+        assert_eq!(amount_in_words(amount("-0.001"), "USD"), "zero dollars");
+    }
+}
@@ -0,0 +1,43 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+pub const IDEMPOTENCY_KEY_HEADER: &str = "Idempotency-Key";
+/// longer keys are rejected rather than stored
+pub const MAX_KEY_LEN: usize = 255;
+
+/// Remembers which transaction an `Idempotency-Key` created, for `ttl`.
+pub struct IdempotencyCache {
+    ttl: Duration,
+    entries: Mutex<HashMap<String, (Uuid, Instant)>>,
+}
+
+impl IdempotencyCache {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// The transaction id recorded for `key`, if it hasn't expired.
+    pub fn get(&self, key: &str) -> Option<Uuid> {
+        let entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        entries
+            .get(key)
+            .filter(|(_, at)| at.elapsed() < self.ttl)
+            .map(|(id, _)| *id)
+    }
+
+    pub fn insert(&self, key: String, id: Uuid) {
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        entries.insert(key, (id, Instant::now()));
+    }
+
+    /// Forget expired keys.
+    pub fn prune(&self) {
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        entries.retain(|_, (_, at)| at.elapsed() < self.ttl);
+    }
+}
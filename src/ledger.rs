@@ -0,0 +1,193 @@
+use crate::Transaction;
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet};
+use std::ops::Deref;
+use uuid::Uuid;
+
+/// The in-memory transaction list plus id and per-user indexes.
+///
+/// Derefs to `[Transaction]` so read paths can iterate and slice as before;
+/// all mutation goes through methods that keep the indexes consistent.
+#[derive(Debug, Default)]
+pub struct Ledger {
+    /// insertion order, which is the order the list is served and persisted in
+    txs: Vec<Transaction>,
+    by_id: HashMap<Uuid, usize>,
+    /// each user's ids, kept in insertion order
+    by_user: HashMap<String, Vec<Uuid>>,
+    /// ids pushed, edited or removed since the last take_changed()
+    changed: HashSet<Uuid>,
+    /// handed out by next_seq(); removals don't give numbers back
+    next_seq: u64,
+    /// counts every change, for collection ETags
+    revision: u64,
+    /// random per ledger, so revisions from before a restart or a restore never match
+    epoch: u64,
+    /// counts removals, the only change that moves rows already in the list
+    removals: u64,
+}
+
+impl Ledger {
+    /// Sequence numbers continue from at least `first_seq`, so rows that now
+    /// live elsewhere (the archive) keep theirs to themselves.
+    /// Rows without a number yet are numbered in stored order.
+    pub fn starting_at(mut txs: Vec<Transaction>, first_seq: u64) -> Self {
+        let mut next_seq = txs
+            .iter()
+            .map(|t| t.seq + 1)
+            .max()
+            .unwrap_or(1)
+            .max(first_seq);
+        for tx in txs.iter_mut().filter(|t| t.seq == 0) {
+            tx.seq = next_seq;
+            next_seq += 1;
+        }
+        let mut ledger = Self {
+            txs,
+            by_id: HashMap::new(),
+            by_user: HashMap::new(),
+            changed: HashSet::new(),
+            next_seq,
+            revision: 0,
+            removals: 0,
+            epoch: Uuid::new_v4().as_u64_pair().0,
+        };
+        ledger.reindex();
+        ledger
+    }
+
+    /// Reserve the sequence number for a transaction about to be pushed.
+    pub fn next_seq(&mut self) -> u64 {
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        seq
+    }
+
+    /// next_seq() for each of a batch, in order.
+    pub fn number(&mut self, txs: &mut [Transaction]) {
+        for tx in txs {
+            tx.seq = self.next_seq();
+        }
+    }
+
+    fn reindex(&mut self) {
+        self.by_id.clear();
+        self.by_user.clear();
+        for (pos, tx) in self.txs.iter().enumerate() {
+            // first occurrence wins, matching the old linear find()
+            if let Entry::Vacant(slot) = self.by_id.entry(tx.id) {
+                slot.insert(pos);
+                self.by_user.entry(tx.user.clone()).or_default().push(tx.id);
+            }
+        }
+    }
+
+    pub fn get(&self, id: &Uuid) -> Option<&Transaction> {
+        self.by_id.get(id).map(|&pos| &self.txs[pos])
+    }
+
+    pub fn get_by_seq(&self, seq: u64) -> Option<&Transaction> {
+        self.txs.iter().find(|t| t.seq == seq)
+    }
+
+    /// Edit a transaction in place, keeping the user index in step if the user
+    /// changes. Returns None when the id is unknown. `f` must not change the id.
+    pub fn update<R>(&mut self, id: &Uuid, f: impl FnOnce(&mut Transaction) -> R) -> Option<R> {
+        let pos = *self.by_id.get(id)?;
+        let tx = &mut self.txs[pos];
+        let before = tx.clone();
+        let result = f(tx);
+        // callers may look and then refuse; that isn't a change
+        if *tx == before {
+            return Some(result);
+        }
+        self.changed.insert(*id);
+        self.revision += 1;
+        let old_user = before.user;
+        if tx.user != old_user {
+            let new_user = tx.user.clone();
+            self.move_user(*id, pos, &old_user, new_user);
+        }
+        Some(result)
+    }
+
+    fn move_user(&mut self, id: Uuid, pos: usize, old_user: &str, new_user: String) {
+        if let Some(ids) = self.by_user.get_mut(old_user) {
+            ids.retain(|i| *i != id);
+            if ids.is_empty() {
+                self.by_user.remove(old_user);
+            }
+        }
+        let by_id = &self.by_id;
+        let ids = self.by_user.entry(new_user).or_default();
+        // ids are ordered by position, so binary search keeps insertion order
+        let at = ids.partition_point(|i| by_id[i] < pos);
+        ids.insert(at, id);
+    }
+
+    /// All of one user's transactions in insertion order, without scanning the rest.
+    pub fn for_user<'a>(&'a self, user: &str) -> impl Iterator<Item = &'a Transaction> + 'a {
+        self.by_user
+            .get(user)
+            .into_iter()
+            .flatten()
+            .map(|id| &self.txs[self.by_id[id]])
+    }
+
+    pub fn push(&mut self, tx: Transaction) {
+        if let Entry::Vacant(slot) = self.by_id.entry(tx.id) {
+            slot.insert(self.txs.len());
+            self.by_user.entry(tx.user.clone()).or_default().push(tx.id);
+        }
+        self.changed.insert(tx.id);
+        self.revision += 1;
+        self.next_seq = self.next_seq.max(tx.seq + 1);
+        self.txs.push(tx);
+    }
+
+    /// Drop every transaction with one of `ids` outright, returning what was removed.
+    pub fn remove(&mut self, ids: &HashSet<Uuid>) -> Vec<Transaction> {
+        let (removed, kept) = std::mem::take(&mut self.txs)
+            .into_iter()
+            .partition(|tx| ids.contains(&tx.id));
+        self.txs = kept;
+        self.reindex();
+        if !removed.is_empty() {
+            self.changed.extend(removed.iter().map(|tx| tx.id));
+            self.revision += 1;
+            self.removals += 1;
+        }
+        removed
+    }
+
+    /// Identifies the current state of the whole list; changes with every
+    /// push, edit and removal.
+    pub fn revision_tag(&self) -> String {
+        format!("{:016x}-{}", self.epoch, self.revision)
+    }
+
+    /// Stays the same for as long as every row keeps its position; pushes
+    /// leave it alone. Lets a reader walk the list across several locks.
+    pub fn layout(&self) -> (u64, u64) {
+        (self.epoch, self.removals)
+    }
+
+    /// Ids touched since the last call, for the write-ahead log.
+    pub fn take_changed(&mut self) -> HashSet<Uuid> {
+        std::mem::take(&mut self.changed)
+    }
+
+    pub fn extend(&mut self, txs: impl IntoIterator<Item = Transaction>) {
+        for tx in txs {
+            self.push(tx);
+        }
+    }
+}
+
+impl Deref for Ledger {
+    type Target = [Transaction];
+
+    fn deref(&self) -> &[Transaction] {
+        &self.txs
+    }
+}
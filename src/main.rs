@@ -1,278 +1,5428 @@
-use actix_web::{App, HttpResponse, HttpServer, Responder, delete, get, post, put, web};
+use actix_web::error::{InternalError, JsonPayloadError, QueryPayloadError};
+use actix_web::http::header::{self, Header};
+use actix_web::{
+    App, HttpRequest, HttpResponse, HttpServer, Responder, delete, get, patch, post, put, web,
+};
+use archive::Archive;
+use audit::{AuditEntry, AuditLog, Diff};
+use backup::Backups;
+use budget::{Budget, BudgetStore, CreateBudget};
+use chrono::{DateTime, Datelike, NaiveDate, Utc};
+use config::{Config, StorageBackend};
+use events::{ChangeFeed, ChangeKind};
+use futures_util::StreamExt;
+use idempotency::{IDEMPOTENCY_KEY_HEADER, IdempotencyCache};
+use ledger::Ledger;
+use metrics::Metrics;
+use middleware::{ApiKey, RateLimiter, ReadOnly};
+use rates::RateTable;
+use receipts::Receipts;
+use recurring::{CreateRecurring, RecurringStore, RecurringTransaction};
+use rust_decimal::{Decimal, RoundingStrategy};
 use serde::{Deserialize, Serialize};
-use std::path::Path;
-use std::sync::Arc;
-use std::time::{SystemTime, UNIX_EPOCH};
-use tokio::fs;
-use tokio::sync::RwLock;
+use statement::{Statement, StatementPeriod};
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use storage::{JsonFileStorage, SqliteStorage, Storage};
+use tokio::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+use tracing_subscriber::EnvFilter;
+use transfer::{CreateTransfer, Transfer};
+use undo::{Inverse, Step, UndoHistory};
+use utoipa::{IntoParams, ToSchema};
+use utoipa_swagger_ui::SwaggerUi;
 use uuid::Uuid;
+use verify::VerifyReport;
+use wal::{WalRecord, WriteAheadLog};
 
-const STORAGE_FILE: &str = "transactions.json";
+mod archive;
+mod audit;
+mod backup;
+mod budget;
+mod config;
+mod envelope;
+mod events;
+mod format;
+mod idempotency;
+mod ledger;
+mod metrics;
+mod middleware;
+mod openapi;
+mod rates;
+mod receipts;
+mod recurring;
+mod statement;
+mod storage;
+mod streaming;
+mod transfer;
+mod undo;
+mod verify;
+mod wal;
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+/// how often the archive job looks for transactions past the retention age
+const ARCHIVE_CHECK_INTERVAL: Duration = Duration::from_secs(60 * 60);
+const RATE_LIMIT_CLEANUP_INTERVAL: Duration = Duration::from_secs(60);
+const IDEMPOTENCY_PRUNE_INTERVAL: Duration = Duration::from_secs(60);
+const DEFAULT_PAGE_LIMIT: usize = 50;
+/// used for new entries unless BOOKKEEPING_DEFAULT_CURRENCY says otherwise
+const DEFAULT_CURRENCY: &str = "USD";
+const DEFAULT_TOP_N: usize = 10;
+const MAX_NOTE_CHARS: usize = 500;
+const MAX_ATTACHMENT_URL_LEN: usize = 2048;
+
+/// Set once at startup from BOOKKEEPING_DEFAULT_CURRENCY.
+static CONFIGURED_CURRENCY: OnceLock<String> = OnceLock::new();
+
+/// Currency for new entries that don't name one.
+fn default_currency() -> String {
+    CONFIGURED_CURRENCY
+        .get()
+        .map_or(DEFAULT_CURRENCY, String::as_str)
+        .to_string()
+}
+
+/// Stored rows without a currency predate the field, when everything was USD,
+/// whatever the configured default is now.
+fn legacy_currency() -> String {
+    DEFAULT_CURRENCY.to_string()
+}
+
+/// Current UNIX time in seconds, falling back to 0 if the clock is before the epoch.
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Trim a note's ends, treating an all-whitespace note as no note.
+fn normalize_note(note: &str) -> Result<Option<String>, &'static str> {
+    let trimmed = note.trim();
+    if trimmed.chars().count() > MAX_NOTE_CHARS {
+        return Err("must be at most 500 characters");
+    }
+    Ok((!trimmed.is_empty()).then(|| trimmed.to_string()))
+}
+
+/// Trim a receipt link and require an absolute http(s) URL; blank means none.
+fn normalize_attachment_url(url: &str) -> Result<Option<String>, &'static str> {
+    let trimmed = url.trim();
+    if trimmed.is_empty() {
+        return Ok(None);
+    }
+    if trimmed.len() > MAX_ATTACHMENT_URL_LEN {
+        return Err("must be at most 2048 characters");
+    }
+    match url::Url::parse(trimmed) {
+        Ok(parsed) if matches!(parsed.scheme(), "http" | "https") => Ok(Some(parsed.into())),
+        _ => Err("must be an absolute http or https URL"),
+    }
+}
+
+/// Grouping key for item names in reports: runs of whitespace become one
+/// space and case is folded, so "Coffee", "coffee " and "COFFEE" count as one
+/// item. The stored name is left as entered.
+fn fold_item(item: &str) -> String {
+    item.split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+}
+
+/// Lowercase and deduplicate tags, keeping the order they were first given in.
+fn normalize_tags(tags: &[String]) -> Result<Vec<String>, &'static str> {
+    let mut normalized: Vec<String> = Vec::with_capacity(tags.len());
+    for tag in tags {
+        let tag = tag.trim().to_lowercase();
+        if tag.is_empty() {
+            return Err("must all be non-empty strings");
+        }
+        if !normalized.contains(&tag) {
+            normalized.push(tag);
+        }
+    }
+    Ok(normalized)
+}
+
+/// Parse an ISO 8601 date-time (`2024-05-01T12:00:00Z`, any offset) or a bare
+/// date (midnight UTC) into UNIX seconds.
+fn parse_iso_timestamp(s: &str) -> Option<u64> {
+    let secs = match DateTime::parse_from_rfc3339(s) {
+        Ok(dt) => dt.timestamp(),
+        Err(_) => NaiveDate::parse_from_str(s, "%Y-%m-%d")
+            .ok()?
+            .and_hms_opt(0, 0, 0)?
+            .and_utc()
+            .timestamp(),
+    };
+    u64::try_from(secs).ok()
+}
+
+/// Accepts `null`, UNIX seconds as a number, or an ISO 8601 string.
+fn deserialize_timestamp<'de, D>(deserializer: D) -> Result<Option<u64>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    struct TimestampVisitor;
+
+    impl<'de> serde::de::Visitor<'de> for TimestampVisitor {
+        type Value = Option<u64>;
+
+        fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+            f.write_str("UNIX seconds or an ISO 8601 date-time string")
+        }
+
+        fn visit_unit<E: serde::de::Error>(self) -> Result<Self::Value, E> {
+            Ok(None)
+        }
+
+        fn visit_none<E: serde::de::Error>(self) -> Result<Self::Value, E> {
+            Ok(None)
+        }
+
+        fn visit_some<D: serde::Deserializer<'de>>(self, d: D) -> Result<Self::Value, D::Error> {
+            d.deserialize_any(self)
+        }
+
+        fn visit_u64<E: serde::de::Error>(self, v: u64) -> Result<Self::Value, E> {
+            Ok(Some(v))
+        }
+
+        fn visit_i64<E: serde::de::Error>(self, v: i64) -> Result<Self::Value, E> {
+            u64::try_from(v)
+                .map(Some)
+                .map_err(|_| E::custom("timestamp must not be before 1970"))
+        }
+
+        fn visit_str<E: serde::de::Error>(self, v: &str) -> Result<Self::Value, E> {
+            let v = v.trim();
+            v.parse::<u64>()
+                .ok()
+                .or_else(|| parse_iso_timestamp(v))
+                .map(Some)
+                .ok_or_else(|| E::custom(format!("invalid timestamp: {:?}", v)))
+        }
+    }
+
+    deserializer.deserialize_any(TimestampVisitor)
+}
+
+/// Symbols a form may leave in front of or behind an amount string.
+const AMOUNT_SYMBOLS: &[char] = &['$', '€', '£', '¥', '₹'];
+
+/// `"$1,234.50"` as 1234.50: currency symbols and whitespace are dropped,
+/// and commas are allowed only as thousands separators, so a decimal comma
+/// (`"12,50"`) is refused rather than read as 1250.
+fn parse_amount_text(s: &str) -> Option<Decimal> {
+    let cleaned: String = s
+        .chars()
+        .filter(|c| !c.is_whitespace() && !AMOUNT_SYMBOLS.contains(c))
+        .collect();
+    let unsigned = cleaned.trim_start_matches(['-', '+']);
+    let whole = unsigned.split('.').next().unwrap_or_default();
+    if whole.contains(',') {
+        let mut groups = whole.split(',');
+        let first = groups.next().unwrap_or_default();
+        if first.is_empty() || first.len() > 3 || groups.any(|g| g.len() != 3) {
+            return None;
+        }
+    }
+    if unsigned.contains(',') && !whole.contains(',') {
+        return None;
+    }
+    cleaned.replace(',', "").parse().ok()
+}
+
+/// A request amount: a JSON number, or a numeric string as form-based
+/// clients tend to send (`"12.50"`, `"$1,234.50"`).
+struct Amount(Decimal);
+
+impl<'de> Deserialize<'de> for Amount {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct AmountVisitor;
+
+        impl<'de> serde::de::Visitor<'de> for AmountVisitor {
+            type Value = Amount;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                f.write_str("a number or a numeric string such as \"12.50\"")
+            }
+
+            fn visit_u64<E: serde::de::Error>(self, v: u64) -> Result<Self::Value, E> {
+                Ok(Amount(Decimal::from(v)))
+            }
+
+            fn visit_i64<E: serde::de::Error>(self, v: i64) -> Result<Self::Value, E> {
+                Ok(Amount(Decimal::from(v)))
+            }
+
+            fn visit_f64<E: serde::de::Error>(self, v: f64) -> Result<Self::Value, E> {
+                // the same conversion a plain Decimal field makes
+                let v = serde::de::IntoDeserializer::<E>::into_deserializer(v);
+                <Decimal as Deserialize>::deserialize(v).map(Amount)
+            }
+
+            fn visit_str<E: serde::de::Error>(self, v: &str) -> Result<Self::Value, E> {
+                parse_amount_text(v)
+                    .map(Amount)
+                    .ok_or_else(|| E::custom(format!("invalid amount: {:?}", v)))
+            }
+        }
+
+        deserializer.deserialize_any(AmountVisitor)
+    }
+}
+
+fn deserialize_amount<'de, D>(deserializer: D) -> Result<Decimal, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    Amount::deserialize(deserializer).map(|a| a.0)
+}
+
+fn deserialize_optional_amount<'de, D>(deserializer: D) -> Result<Option<Decimal>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    Option::<Amount>::deserialize(deserializer).map(|a| a.map(|a| a.0))
+}
+
+/// Digits after the decimal point in a currency's minor unit; ISO 4217 has two
+/// for everything not listed here.
+fn currency_decimals(code: &str) -> u32 {
+    match code {
+        "BIF" | "CLP" | "DJF" | "GNF" | "ISK" | "JPY" | "KMF" | "KRW" | "PYG" | "RWF" | "UGX"
+        | "VND" | "VUV" | "XAF" | "XOF" | "XPF" => 0,
+        "BHD" | "IQD" | "JOD" | "KWD" | "LYD" | "OMR" | "TND" => 3,
+        _ => 2,
+    }
+}
+
+/// Round half away from zero to the currency's minor unit.
+fn round_amount(amount: Decimal, currency: &str) -> Decimal {
+    amount.round_dp_with_strategy(
+        currency_decimals(currency),
+        RoundingStrategy::MidpointAwayFromZero,
+    )
+}
+
+/// Round each per-currency total in place.
+fn round_totals(totals: &mut BTreeMap<&str, Decimal>) {
+    for (currency, total) in totals.iter_mut() {
+        *total = round_amount(*total, currency);
+    }
+}
+
+/// ISO 4217 codes are exactly three uppercase ASCII letters.
+fn is_valid_currency(code: &str) -> bool {
+    code.len() == 3 && code.bytes().all(|b| b.is_ascii_uppercase())
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, ToSchema)]
 pub struct Transaction {
     pub id: Uuid,
+    /// human-friendly number (1, 2, ...) in creation order. Numbering resumes
+    /// after the highest stored or archived one on startup; files written
+    /// before it existed are numbered on load.
+    #[serde(default)]
+    pub seq: u64,
     pub user: String,
     pub item: String,
-    pub amount: f64,
+    pub amount: Decimal,
     /// UNIX timestamp (seconds since epoch)
     pub timestamp: u64,
+    /// e.g. "groceries", "rent"; absent in files written before categories existed
+    #[serde(default)]
+    pub category: Option<String>,
+    /// ISO 4217 code; files written before currencies existed load as USD
+    #[serde(default = "legacy_currency")]
+    pub currency: String,
+    /// soft-deleted rows stay on disk for audit history but are hidden by default
+    #[serde(default)]
+    pub deleted: bool,
+    /// bumped on every successful mutation, used for optimistic concurrency
+    #[serde(default = "initial_version")]
+    pub version: u64,
+    /// free-form memo; internal newlines are kept
+    #[serde(default)]
+    pub note: Option<String>,
+    /// lowercase, deduplicated labels in first-seen order
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// shared by the two legs of a transfer
+    #[serde(default)]
+    pub transfer_id: Option<Uuid>,
+    /// link to a stored receipt image or PDF
+    #[serde(default)]
+    pub attachment_url: Option<String>,
+    /// where an uploaded receipt was saved; fetch it from /transactions/{id}/receipt
+    #[serde(default)]
+    pub receipt: Option<String>,
+    /// set by /admin/close-period; a locked entry can no longer be changed
+    #[serde(default)]
+    pub locked: bool,
+}
+
+fn initial_version() -> u64 {
+    1
+}
+
+impl Transaction {
+    /// Record a successful mutation.
+    fn bump_version(&mut self) {
+        self.version = self.version.wrapping_add(1);
+    }
+}
+
+/// Direction of an entry. Debits (purchases) are stored as positive amounts,
+/// credits (refunds, income) as negative ones.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum EntryKind {
+    #[default]
+    Debit,
+    Credit,
+}
+
+impl EntryKind {
+    /// Kind implied by an already-stored signed amount.
+    fn of(amount: Decimal) -> Self {
+        if amount.is_sign_negative() {
+            EntryKind::Credit
+        } else {
+            EntryKind::Debit
+        }
+    }
+
+    /// Apply this kind's sign to a non-negative magnitude.
+    fn signed(self, magnitude: Decimal) -> Decimal {
+        match self {
+            EntryKind::Debit => magnitude,
+            EntryKind::Credit => -magnitude,
+        }
+    }
+}
+
+const NEGATIVE_AMOUNT_ERROR: &str =
+    "must be non-negative; use kind \"credit\" for refunds instead of a negative amount";
+const CURRENCY_ERROR: &str = "must be a three-letter uppercase ISO 4217 code";
+
+/// One failed check, reported to clients as `{ "field": ..., "message": ... }`.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct FieldError {
+    pub field: &'static str,
+    pub message: &'static str,
+}
+
+impl FieldError {
+    fn new(field: &'static str, message: &'static str) -> Self {
+        Self { field, message }
+    }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+/// 400 response listing every field that failed validation.
+fn validation_failed(errors: Vec<FieldError>) -> HttpResponse {
+    HttpResponse::BadRequest().json(serde_json::json!({ "errors": errors }))
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct CreateTransaction {
     pub user: String,
     pub item: String,
-    pub amount: f64,
-    /// optional: if omitted server will fill current timestamp
+    /// non-negative magnitude; the stored sign comes from `kind`. A number or
+    /// a numeric string
+    #[serde(deserialize_with = "deserialize_amount")]
+    pub amount: Decimal,
+    /// defaults to debit when omitted
+    #[serde(default)]
+    pub kind: Option<EntryKind>,
+    /// optional: if omitted server will fill current timestamp.
+    /// UNIX seconds or an ISO 8601 string
+    #[serde(
+        default,
+        deserialize_with = "deserialize_timestamp",
+        skip_serializing_if = "Option::is_none"
+    )]
     pub timestamp: Option<u64>,
+    #[serde(default)]
+    pub category: Option<String>,
+    #[serde(default = "default_currency")]
+    pub currency: String,
+    #[serde(default)]
+    pub note: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// absolute http(s) link to a receipt
+    #[serde(default)]
+    pub attachment_url: Option<String>,
+}
+
+impl CreateTransaction {
+    /// Check every field, collecting all failures rather than stopping at the first.
+    fn validate(&self) -> Result<(), Vec<FieldError>> {
+        let mut errors = Vec::new();
+        if self.user.trim().is_empty() {
+            errors.push(FieldError::new("user", "required"));
+        }
+        if self.item.trim().is_empty() {
+            errors.push(FieldError::new("item", "required"));
+        }
+        if self.amount.is_sign_negative() {
+            errors.push(FieldError::new("amount", NEGATIVE_AMOUNT_ERROR));
+        }
+        if self.category.as_ref().is_some_and(|c| c.trim().is_empty()) {
+            errors.push(FieldError::new(
+                "category",
+                "must be a non-empty string when provided",
+            ));
+        }
+        if !is_valid_currency(&self.currency) {
+            errors.push(FieldError::new("currency", CURRENCY_ERROR));
+        }
+        if let Some(Err(msg)) = self.note.as_deref().map(normalize_note) {
+            errors.push(FieldError::new("note", msg));
+        }
+        if let Err(msg) = normalize_tags(&self.tags) {
+            errors.push(FieldError::new("tags", msg));
+        }
+        if let Some(Err(msg)) = self.attachment_url.as_deref().map(normalize_attachment_url) {
+            errors.push(FieldError::new("attachment_url", msg));
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Build a stored transaction with a fresh id; call validate() first.
+    fn to_transaction(&self) -> Transaction {
+        Transaction {
+            id: Uuid::new_v4(),
+            // assigned by the ledger when it is pushed
+            seq: 0,
+            user: self.user.trim().to_string(),
+            item: self.item.trim().to_string(),
+            amount: round_amount(
+                self.kind.unwrap_or_default().signed(self.amount),
+                &self.currency,
+            ),
+            timestamp: self.timestamp.unwrap_or_else(now_secs),
+            category: self.category.as_ref().map(|c| c.trim().to_string()),
+            currency: self.currency.clone(),
+            deleted: false,
+            version: initial_version(),
+            note: self
+                .note
+                .as_deref()
+                .and_then(|n| normalize_note(n).ok().flatten()),
+            tags: normalize_tags(&self.tags).unwrap_or_default(),
+            transfer_id: None,
+            attachment_url: self
+                .attachment_url
+                .as_deref()
+                .and_then(|u| normalize_attachment_url(u).ok().flatten()),
+            receipt: None,
+            locked: false,
+        }
+    }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Default, Serialize, Deserialize, ToSchema)]
 pub struct UpdateTransaction {
     pub user: Option<String>,
     pub item: Option<String>,
-    pub amount: Option<f64>,
+    /// non-negative magnitude; keeps the existing sign unless `kind` is given.
+    /// A number or a numeric string
+    #[serde(default, deserialize_with = "deserialize_optional_amount")]
+    pub amount: Option<Decimal>,
+    #[serde(default)]
+    pub kind: Option<EntryKind>,
+    /// UNIX seconds or an ISO 8601 string
+    #[serde(default, deserialize_with = "deserialize_timestamp")]
     pub timestamp: Option<u64>,
+    #[serde(default)]
+    pub category: Option<String>,
+    pub currency: Option<String>,
+    /// optional alternative to an If-Match header
+    pub version: Option<u64>,
+    /// an empty or whitespace-only note clears it
+    #[serde(default)]
+    pub note: Option<String>,
+    /// replaces the whole tag list when present; `[]` clears it
+    #[serde(default)]
+    pub tags: Option<Vec<String>>,
+    /// an empty string clears it
+    #[serde(default)]
+    pub attachment_url: Option<String>,
 }
 
-#[derive(Clone)]
-struct AppState {
-    /// async RwLock protects the vector; Arc-wrap via web::Data
-    transactions: Arc<RwLock<Vec<Transaction>>>,
-    file_path: String,
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct Pagination {
+    /// defaults to DEFAULT_PAGE_LIMIT when omitted
+    pub limit: Option<usize>,
+    /// defaults to 0 when omitted. Pages shift when rows are added or removed
+    /// in between requests; prefer `after` for walking the whole list.
+    pub offset: Option<usize>,
+    /// id from a previous page's `next_cursor`; the page starts right after it.
+    /// Only valid with the (default) timestamp sort.
+    pub after: Option<String>,
 }
 
-impl AppState {
-    async fn persist(&self) -> std::io::Result<()> {
-        // Take a snapshot of the data under a read lock, then write the file asynchronously.
-        let snapshot = {
-            let read_guard = self.transactions.read().await;
-            serde_json::to_vec_pretty(&*read_guard)?
-        };
+/// Query filters shared by every endpoint that selects transactions (list,
+/// count, export). Each unset field matches everything, so a new filter is one
+/// field here plus one clause in `allows`.
+#[derive(Debug, Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct TransactionFilter {
+    /// inclusive lower bound (UNIX seconds)
+    pub from: Option<u64>,
+    /// inclusive upper bound (UNIX seconds)
+    pub to: Option<u64>,
+    /// exact match on the stored (trimmed) user name
+    pub user: Option<String>,
+    /// exact match on the stored (trimmed) category
+    pub category: Option<String>,
+    /// matched case-insensitively against the stored (lowercase) tags
+    pub tag: Option<String>,
+    /// inclusive bounds on the signed stored amount
+    pub min_amount: Option<Decimal>,
+    pub max_amount: Option<Decimal>,
+    /// true for rows with a category, false for rows without one
+    pub has_category: Option<bool>,
+    /// true for rows with a note, false for rows without one
+    pub has_note: Option<bool>,
+    /// true for rows with at least one tag, false for untagged rows
+    pub has_tags: Option<bool>,
+    /// true for rows with an attachment URL, false for rows without one
+    pub has_attachment: Option<bool>,
+    /// true for rows with an uploaded receipt, false for rows without one
+    pub has_receipt: Option<bool>,
+    /// soft-deleted transactions are hidden unless this is true
+    #[serde(default)]
+    pub include_deleted: bool,
+}
 
-        // Write to temp file then rename for atomicity
-        let tmp_path = format!("{}.tmp", &self.file_path);
-        fs::write(&tmp_path, snapshot).await?;
-        fs::rename(&tmp_path, &self.file_path).await?;
+impl TransactionFilter {
+    fn validate(&self) -> Result<(), &'static str> {
+        if let (Some(min), Some(max)) = (self.min_amount, self.max_amount)
+            && min > max
+        {
+            return Err("min_amount must not exceed max_amount");
+        }
+        if self.category.is_some() && self.has_category == Some(false) {
+            return Err("category and has_category=false can never both match");
+        }
+        if self.tag.is_some() && self.has_tags == Some(false) {
+            return Err("tag and has_tags=false can never both match");
+        }
         Ok(())
     }
 
-    async fn load(file_path: impl Into<String>) -> std::io::Result<Vec<Transaction>> {
-        let file_path = file_path.into();
-        if Path::new(&file_path).exists() {
-            let data = fs::read(&file_path).await?;
-            let txs: Vec<Transaction> = serde_json::from_slice(&data).unwrap_or_default();
-            Ok(txs)
-        } else {
-            Ok(Vec::new())
-        }
+    fn allows(&self, tx: &Transaction) -> bool {
+        (self.include_deleted || !tx.deleted)
+            && self.from.is_none_or(|from| tx.timestamp >= from)
+            && self.to.is_none_or(|to| tx.timestamp <= to)
+            && self
+                .user
+                .as_deref()
+                .is_none_or(|user| tx.user == user.trim())
+            && self
+                .category
+                .as_deref()
+                .is_none_or(|category| tx.category.as_deref() == Some(category.trim()))
+            && self
+                .tag
+                .as_deref()
+                .is_none_or(|tag| tx.tags.contains(&tag.trim().to_lowercase()))
+            && self.min_amount.is_none_or(|min| tx.amount >= min)
+            && self.max_amount.is_none_or(|max| tx.amount <= max)
+            && self
+                .has_category
+                .is_none_or(|has| tx.category.is_some() == has)
+            && self.has_note.is_none_or(|has| tx.note.is_some() == has)
+            && self.has_tags.is_none_or(|has| tx.tags.is_empty() != has)
+            && self
+                .has_attachment
+                .is_none_or(|has| tx.attachment_url.is_some() == has)
+            && self
+                .has_receipt
+                .is_none_or(|has| tx.receipt.is_some() == has)
+    }
+
+    /// The transactions this filter lets through, in stored order.
+    fn apply_filter<'a>(
+        &'a self,
+        txs: &'a [Transaction],
+    ) -> impl Iterator<Item = &'a Transaction> + 'a {
+        txs.iter().filter(|tx| self.allows(tx))
     }
 }
 
-#[post("/transactions")]
-async fn create_transaction(
-    state: web::Data<AppState>,
-    payload: web::Json<CreateTransaction>,
-) -> impl Responder {
-    // Basic validation
-    if payload.user.trim().is_empty() || payload.item.trim().is_empty() {
-        return HttpResponse::BadRequest().json(serde_json::json!({
-            "error": "user and item must be non-empty strings"
-        }));
+#[derive(Debug, Deserialize, Clone, Copy, Default, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum SortKey {
+    #[default]
+    Timestamp,
+    Amount,
+    User,
+    Item,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, Default, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    Asc,
+    #[default]
+    Desc,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct Sorting {
+    /// defaults to timestamp, newest first
+    pub sort: Option<SortKey>,
+    pub order: Option<SortOrder>,
+}
+
+impl Sorting {
+    fn sort(&self, txs: &mut [&Transaction]) {
+        let key = self.sort.unwrap_or_default();
+        // stable sort keeps insertion order for ties
+        txs.sort_by(|a, b| {
+            let ord = match key {
+                // ties broken by id so cursors have a total order to resume from
+                SortKey::Timestamp => a.timestamp.cmp(&b.timestamp).then(a.id.cmp(&b.id)),
+                SortKey::Amount => a.amount.cmp(&b.amount),
+                SortKey::User => a.user.cmp(&b.user),
+                SortKey::Item => a.item.cmp(&b.item),
+            };
+            match self.order.unwrap_or_default() {
+                SortOrder::Asc => ord,
+                SortOrder::Desc => ord.reverse(),
+            }
+        });
     }
-    if !payload.amount.is_finite() {
-        return HttpResponse::BadRequest().json(serde_json::json!({
-            "error": "amount must be a finite number"
-        }));
+}
+
+/// One piece of POST /transactions/{id}/split. User, timestamp, currency,
+/// note, tags and debit/credit kind all come from the original.
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct SplitPart {
+    pub item: String,
+    /// non-negative magnitude; a number or a numeric string
+    #[serde(deserialize_with = "deserialize_amount")]
+    pub amount: Decimal,
+    #[serde(default)]
+    pub category: Option<String>,
+}
+
+impl SplitPart {
+    fn validate(&self) -> Result<(), Vec<FieldError>> {
+        let mut errors = Vec::new();
+        if self.item.trim().is_empty() {
+            errors.push(FieldError::new("item", "required"));
+        }
+        if self.amount.is_sign_negative() {
+            errors.push(FieldError::new("amount", "must be non-negative"));
+        }
+        if self.category.as_ref().is_some_and(|c| c.trim().is_empty()) {
+            errors.push(FieldError::new(
+                "category",
+                "must be a non-empty string when provided",
+            ));
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
     }
+}
 
-    let ts = payload.timestamp.unwrap_or_else(|| {
-        SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .map(|d| d.as_secs())
-            .unwrap_or(0)
-    });
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct CreateOptions {
+    /// skip the duplicate check
+    #[serde(default)]
+    pub force: bool,
+}
 
-    let tx = Transaction {
-        id: Uuid::new_v4(),
-        user: payload.user.trim().to_string(),
-        item: payload.item.trim().to_string(),
-        amount: payload.amount,
-        timestamp: ts,
-    };
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ImportOptions {
+    /// parse and validate only; nothing is stored
+    #[serde(default)]
+    pub dry_run: bool,
+}
 
-    {
-        // acquire write lock, mutate, then release before any await
-        let mut write_guard = state.transactions.write().await;
-        write_guard.push(tx.clone());
-    } // lock released here
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct DeletedFilter {
+    /// soft-deleted transactions are hidden unless this is true
+    #[serde(default)]
+    pub include_deleted: bool,
+}
 
-    // persist asynchronously
-    if let Err(e) = state.persist().await {
-        eprintln!("Failed to persist transactions: {}", e);
-        return HttpResponse::InternalServerError().json(serde_json::json!({
-            "error": "failed to save transaction"
-        }));
+impl DeletedFilter {
+    fn allows(&self, tx: &Transaction) -> bool {
+        self.include_deleted || !tx.deleted
     }
+}
 
-    HttpResponse::Created().json(tx)
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct UserFilter {
+    /// exact match on the stored (trimmed) user name
+    pub user: Option<String>,
 }
 
-#[get("/transactions")]
-async fn list_transactions(state: web::Data<AppState>) -> impl Responder {
-    let read_guard = state.transactions.read().await;
-    HttpResponse::Ok().json(read_guard.clone())
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct SearchQuery {
+    pub q: Option<String>,
 }
 
-#[get("/transactions/{id}")]
-async fn get_transaction(state: web::Data<AppState>, path: web::Path<String>) -> impl Responder {
-    let id_str = path.into_inner();
-    let id = match Uuid::parse_str(&id_str) {
-        Ok(u) => u,
-        Err(_) => {
-            return HttpResponse::BadRequest().json(serde_json::json!({"error":"invalid uuid"}));
-        }
-    };
+/// Whether the item or user contains `needle`, which must already be lowercase.
+fn matches_search(tx: &Transaction, needle: &str) -> bool {
+    tx.item.to_lowercase().contains(needle) || tx.user.to_lowercase().contains(needle)
+}
 
-    let read_guard = state.transactions.read().await;
-    if let Some(tx) = read_guard.iter().find(|t| t.id == id) {
-        HttpResponse::Ok().json(tx.clone())
-    } else {
-        HttpResponse::NotFound().json(serde_json::json!({"error":"not found"}))
-    }
+/// One row of the CSV export; column order defines the header row.
+#[derive(Debug, Serialize)]
+struct CsvExportRow<'a> {
+    id: Uuid,
+    user: &'a str,
+    item: &'a str,
+    amount: Decimal,
+    timestamp: u64,
 }
 
-#[put("/transactions/{id}")]
-async fn update_transaction(
-    state: web::Data<AppState>,
-    path: web::Path<String>,
-    payload: web::Json<UpdateTransaction>,
-) -> impl Responder {
-    let id_str = path.into_inner();
-    let id = match Uuid::parse_str(&id_str) {
-        Ok(u) => u,
-        Err(_) => {
-            return HttpResponse::BadRequest().json(serde_json::json!({"error":"invalid uuid"}));
-        }
-    };
+/// One row of a CSV import; columns are matched by header name, extras are ignored.
+#[derive(Debug, Deserialize)]
+struct CsvImportRow {
+    user: String,
+    item: String,
+    /// non-negative magnitude; the stored sign comes from `kind`. Symbols and
+    /// thousands commas are allowed, as in JSON bodies
+    #[serde(deserialize_with = "deserialize_amount")]
+    amount: Decimal,
+    /// `debit` (the default when the column or cell is empty) or `credit`
+    #[serde(default)]
+    kind: Option<EntryKind>,
+    timestamp: Option<u64>,
+}
 
-    {
-        let mut write_guard = state.transactions.write().await;
-        if let Some(tx) = write_guard.iter_mut().find(|t| t.id == id) {
-            if let Some(user) = &payload.user {
-                if user.trim().is_empty() {
-                    return HttpResponse::BadRequest()
-                        .json(serde_json::json!({"error":"user cannot be empty"}));
-                }
-                tx.user = user.trim().to_string();
-            }
-            if let Some(item) = &payload.item {
-                if item.trim().is_empty() {
-                    return HttpResponse::BadRequest()
-                        .json(serde_json::json!({"error":"item cannot be empty"}));
-                }
-                tx.item = item.trim().to_string();
-            }
-            if let Some(amount) = payload.amount {
-                if !amount.is_finite() {
-                    return HttpResponse::BadRequest()
-                        .json(serde_json::json!({"error":"amount must be finite"}));
-                }
-                tx.amount = amount;
+#[derive(Debug, Serialize)]
+struct ImportError {
+    line: u64,
+    error: String,
+}
+
+/// Parse and validate an import body, returning the accepted transactions and per-line errors.
+fn parse_csv_import(body: &[u8]) -> Result<(Vec<Transaction>, Vec<ImportError>), csv::Error> {
+    let mut reader = csv::ReaderBuilder::new()
+        .flexible(true)
+        .trim(csv::Trim::All)
+        .from_reader(body);
+    let headers = reader.headers()?.clone();
+
+    let mut accepted = Vec::new();
+    let mut errors = Vec::new();
+    for record in reader.records() {
+        let record = match record {
+            Ok(r) => r,
+            Err(e) => {
+                let line = e.position().map(|p| p.line()).unwrap_or(0);
+                errors.push(ImportError {
+                    line,
+                    error: e.to_string(),
+                });
+                continue;
             }
-            if let Some(ts) = payload.timestamp {
-                tx.timestamp = ts;
+        };
+        let line = record.position().map(|p| p.line()).unwrap_or(0);
+        let row: CsvImportRow = match record.deserialize(Some(&headers)) {
+            Ok(r) => r,
+            Err(e) => {
+                errors.push(ImportError {
+                    line,
+                    error: e.to_string(),
+                });
+                continue;
             }
-        } else {
-            return HttpResponse::NotFound().json(serde_json::json!({"error":"not found"}));
+        };
+        if row.user.is_empty() || row.item.is_empty() {
+            errors.push(ImportError {
+                line,
+                error: "user and item must be non-empty strings".to_string(),
+            });
+            continue;
         }
-    } // lock released before await
-
-    if let Err(e) = state.persist().await {
-        eprintln!("Failed to persist after update: {}", e);
-        return HttpResponse::InternalServerError()
-            .json(serde_json::json!({"error":"failed to save changes"}));
+        if row.amount.is_sign_negative() {
+            errors.push(ImportError {
+                line,
+                error: format!("amount {}", NEGATIVE_AMOUNT_ERROR),
+            });
+            continue;
+        }
+        let amount = row.kind.unwrap_or_default().signed(row.amount);
+        accepted.push(Transaction {
+            id: Uuid::new_v4(),
+            seq: 0,
+            user: row.user,
+            item: row.item,
+            amount: round_amount(amount, &default_currency()),
+            timestamp: row.timestamp.unwrap_or_else(now_secs),
+            category: None,
+            currency: default_currency(),
+            deleted: false,
+            version: initial_version(),
+            note: None,
+            tags: Vec::new(),
+            transfer_id: None,
+            attachment_url: None,
+            receipt: None,
+            locked: false,
+        });
     }
+    Ok((accepted, errors))
+}
 
-    // return the updated item
-    let read_guard = state.transactions.read().await;
-    let updated = read_guard.iter().find(|t| t.id == id).cloned().unwrap();
-    HttpResponse::Ok().json(updated)
+struct AppState {
+    /// async RwLock protects the vector; Arc-wrap via web::Data
+    transactions: Arc<RwLock<Ledger>>,
+    storage: Box<dyn Storage + Send + Sync>,
+    /// changes waiting for the flusher, replayed on startup after a crash
+    wal: WriteAheadLog,
+    /// set by mutations, cleared by the background flusher
+    dirty: AtomicBool,
+    metrics: Metrics,
+    recurring: RecurringStore,
+    budgets: BudgetStore,
+    /// Idempotency-Key -> transaction created for it
+    idempotency: IdempotencyCache,
+    backups: Backups,
+    /// exchange rates for converted report totals
+    rates: RateTable,
+    /// live change notifications for /ws and /events subscribers
+    events: ChangeFeed,
+    undo: UndoHistory,
+    audit: AuditLog,
+    archive: Archive,
+    receipts: Receipts,
+    config: Config,
+    /// for the uptime in /stats
+    started: Instant,
+    /// UNIX seconds of the last full save to storage; 0 until the first one
+    last_saved: AtomicU64,
 }
 
-#[delete("/transactions/{id}")]
-async fn delete_transaction(state: web::Data<AppState>, path: web::Path<String>) -> impl Responder {
-    let id_str = path.into_inner();
-    let id = match Uuid::parse_str(&id_str) {
-        Ok(u) => u,
-        Err(_) => {
-            return HttpResponse::BadRequest().json(serde_json::json!({"error":"invalid uuid"}));
+impl AppState {
+    /// The ledger for reading, or None once `lock_timeout` has passed without
+    /// getting it; handlers answer that with `lock_timeout()`.
+    async fn read_ledger(&self) -> Option<RwLockReadGuard<'_, Ledger>> {
+        self.within_lock_timeout("read", self.transactions.read())
+            .await
+    }
+
+    /// As `read_ledger`, for changing it.
+    async fn write_ledger(&self) -> Option<RwLockWriteGuard<'_, Ledger>> {
+        self.within_lock_timeout("write", self.transactions.write())
+            .await
+    }
+
+    async fn within_lock_timeout<G>(
+        &self,
+        access: &str,
+        acquire: impl Future<Output = G>,
+    ) -> Option<G> {
+        let Some(limit) = self.config.lock_timeout else {
+            return Some(acquire.await);
+        };
+        match tokio::time::timeout(limit, acquire).await {
+            Ok(guard) => Some(guard),
+            Err(_) => {
+                // something is holding the lock far longer than any handler should
+                tracing::error!(
+                    access,
+                    timeout_ms = limit.as_millis() as u64,
+                    "Timed out waiting for the transaction lock"
+                );
+                None
+            }
         }
-    };
+    }
 
-    {
-        let mut write_guard = state.transactions.write().await;
-        let initial_len = write_guard.len();
-        write_guard.retain(|t| t.id != id);
-        if write_guard.len() == initial_len {
-            return HttpResponse::NotFound().json(serde_json::json!({"error":"not found"}));
+    /// Called by mutating handlers. With debouncing enabled this logs the changed
+    /// rows to the write-ahead log and marks the state dirty; the background
+    /// flusher writes it out shortly after.
+    async fn persist(&self) -> std::io::Result<()> {
+        if self.config.flush_interval.is_none() {
+            // the full save covers every change, so the log has nothing to hold
+            self.write_ledger()
+                .await
+                .ok_or_else(lock_timed_out)?
+                .take_changed();
+            return self.flush().await;
         }
+        // taken before the ledger, as flush() does, and held until the records are synced
+        let appender = self.wal.appender().await;
+        let mut write_guard = self.write_ledger().await.ok_or_else(lock_timed_out)?;
+        let records = WalRecord::drain(&mut write_guard);
+        drop(write_guard);
+        self.dirty.store(true, Ordering::Release);
+        tracing::debug!(
+            records = records.len(),
+            "Logged changes until the next flush"
+        );
+        appender.append(&records).await
     }
 
-    if let Err(e) = state.persist().await {
-        eprintln!("Failed to persist after delete: {}", e);
-        return HttpResponse::InternalServerError()
-            .json(serde_json::json!({"error":"failed to persist delete"}));
+    /// Write the current state to storage right now.
+    async fn flush(&self) -> std::io::Result<()> {
+        // waits out any save in progress, so this snapshot is never older than the last one written
+        let checkpoint = self.wal.checkpoint().await;
+        // Snapshot under a read lock so writers aren't blocked on disk I/O.
+        let snapshot = self
+            .read_ledger()
+            .await
+            .ok_or_else(lock_timed_out)?
+            .to_vec();
+        let _timer = self.metrics.persist_duration.start_timer();
+        checkpoint.save(&*self.storage, &snapshot).await?;
+        self.last_saved.store(now_secs(), Ordering::Release);
+        tracing::debug!(count = snapshot.len(), "Saved transactions to storage");
+        checkpoint.truncate().await
     }
 
-    HttpResponse::NoContent().finish()
+    /// Tell live subscribers and the audit log about a change that has been persisted.
+    async fn notify(&self, kind: ChangeKind, tx: &Transaction) {
+        self.events.publish(kind, tx);
+        if let Err(e) = self.audit.record(kind, tx).await {
+            // the change itself is already saved, so this only costs history
+            tracing::error!(error = %e, id = %tx.id, "Failed to append audit entry");
+        }
+    }
+
+    /// Snapshot the ledger into a new backup file, returning its name and size.
+    async fn backup(&self) -> std::io::Result<(String, usize)> {
+        let snapshot = self
+            .read_ledger()
+            .await
+            .ok_or_else(lock_timed_out)?
+            .to_vec();
+        let name = self.backups.create(&snapshot).await?;
+        Ok((name, snapshot.len()))
+    }
+
+    /// `path` as clients reach it, under the configured base path.
+    fn url(&self, path: &str) -> String {
+        format!("{}{}", self.config.base_path, path)
+    }
+
+    /// Flush only if something changed since the last successful flush.
+    async fn flush_if_dirty(&self) -> std::io::Result<()> {
+        if !self.dirty.swap(false, Ordering::AcqRel) {
+            return Ok(());
+        }
+        let result = self.flush().await;
+        if result.is_err() {
+            // keep the change pending so the next tick retries it
+            self.dirty.store(true, Ordering::Release);
+        }
+        result
+    }
 }
 
-#[get("/users/{user}/summary")]
-async fn user_summary(state: web::Data<AppState>, path: web::Path<String>) -> impl Responder {
+/// Final write on shutdown. Holds the write lock so nothing can change underneath it.
+async fn shutdown(state: &AppState) -> std::io::Result<()> {
+    let checkpoint = state.wal.checkpoint().await;
+    let write_guard = state.write_ledger().await.ok_or_else(lock_timed_out)?;
+    checkpoint.save(&*state.storage, &write_guard).await?;
+    checkpoint.truncate().await?;
+    state.dirty.store(false, Ordering::Release);
+    state.storage.cleanup().await?;
+    tracing::info!(
+        count = write_guard.len(),
+        "Flushed transactions to storage on shutdown"
+    );
+    Ok(())
+}
+
+/// Resolves on SIGINT (Ctrl-C) or, on unix, SIGTERM.
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            tracing::error!(error = %e, "Failed to listen for Ctrl-C");
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        use tokio::signal::unix::{SignalKind, signal};
+        match signal(SignalKind::terminate()) {
+            Ok(mut sigterm) => {
+                sigterm.recv().await;
+            }
+            Err(e) => {
+                tracing::error!(error = %e, "Failed to listen for SIGTERM");
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {}
+        _ = terminate => {}
+    }
+}
+
+/// Turn recurring templates into transactions as they come due. The first tick
+/// fires immediately, catching up on anything missed while the server was down.
+async fn run_recurring(state: web::Data<AppState>, interval: Duration) {
+    let mut ticker = tokio::time::interval(interval);
+    ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    loop {
+        ticker.tick().await;
+        if let Err(e) = materialize_recurring(&state).await {
+            tracing::error!(error = %e, "Failed to materialize recurring transactions");
+        }
+    }
+}
+
+async fn materialize_recurring(state: &AppState) -> std::io::Result<()> {
+    let mut templates = state.recurring.templates.write().await;
+    // advanced on a copy, so a lock timeout leaves the entries due for the next tick
+    let mut advanced = templates.clone();
+    let mut due = RecurringStore::materialize(&mut advanced, now_secs());
+    if due.is_empty() {
+        return Ok(());
+    }
+    let count = due.len();
+    {
+        let mut write_guard = state.write_ledger().await.ok_or_else(lock_timed_out)?;
+        write_guard.number(&mut due);
+        write_guard.extend(due.iter().cloned());
+    }
+    *templates = advanced;
+    // ledger first: if the template file lags behind we re-create entries rather than lose them
+    state.persist().await?;
+    state.recurring.save(&templates).await?;
+    state.metrics.created.inc_by(count as u64);
+    for tx in &due {
+        state.notify(ChangeKind::Created, tx).await;
+    }
+    tracing::info!(count, "Materialized recurring transactions");
+    Ok(())
+}
+
+async fn run_backups(state: web::Data<AppState>, interval: Duration) {
+    let mut ticker = tokio::time::interval(interval);
+    ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    // skip the immediate first tick; the state was only just loaded
+    ticker.tick().await;
+    loop {
+        ticker.tick().await;
+        match state.backup().await {
+            Ok((name, count)) => tracing::info!(backup = %name, count, "Wrote scheduled backup"),
+            Err(e) => tracing::error!(error = %e, "Failed to write scheduled backup"),
+        }
+    }
+}
+
+async fn run_archive(state: web::Data<AppState>, older_than_days: u64) {
+    let mut ticker = tokio::time::interval(ARCHIVE_CHECK_INTERVAL);
+    ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    loop {
+        ticker.tick().await;
+        let cutoff = now_secs().saturating_sub(older_than_days.saturating_mul(86_400));
+        match archive_older_than(&state, cutoff).await {
+            Ok(0) => {}
+            Ok(count) => tracing::info!(count, cutoff, "Archived old transactions"),
+            Err(e) => tracing::error!(error = %e, "Failed to archive old transactions"),
+        }
+    }
+}
+
+/// Move every transaction dated before `cutoff` into the archive.
+async fn archive_older_than(state: &AppState, cutoff: u64) -> std::io::Result<usize> {
+    // taken before the ledger, as flush() does; until it is released no save can
+    // store the ledger without these rows, so on disk they exist twice rather than
+    // not at all
+    let checkpoint = state.wal.checkpoint().await;
+    let moved = {
+        // copied and removed in one go so nothing edits a row in between
+        let mut write_guard = state.write_ledger().await.ok_or_else(lock_timed_out)?;
+        let ids: HashSet<Uuid> = write_guard
+            .iter()
+            .filter(|t| t.timestamp < cutoff)
+            .map(|t| t.id)
+            .collect();
+        write_guard.remove(&ids)
+    };
+    if moved.is_empty() {
+        return Ok(0);
+    }
+    let count = moved.len();
+    // file I/O without the ledger lock, so readers and writers carry on meanwhile
+    if let Err(e) = state.archive.append(moved.clone()).await {
+        // unbounded on purpose: until they are back these rows exist nowhere but here
+        state.transactions.write().await.extend(moved);
+        return Err(e);
+    }
+    drop(checkpoint);
+    state.persist().await?;
+    Ok(count)
+}
+
+/// Drop expired idempotency keys so the map doesn't grow without bound.
+async fn run_idempotency_prune(state: web::Data<AppState>) {
+    let mut ticker = tokio::time::interval(IDEMPOTENCY_PRUNE_INTERVAL);
+    loop {
+        ticker.tick().await;
+        state.idempotency.prune();
+    }
+}
+
+/// Background task that writes dirty state to storage at most once per interval.
+async fn run_flusher(state: web::Data<AppState>, interval: Duration) {
+    let mut ticker = tokio::time::interval(interval);
+    ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    loop {
+        ticker.tick().await;
+        if let Err(e) = state.flush_if_dirty().await {
+            tracing::error!(error = %e, "Failed to flush transactions");
+        }
+    }
+}
+
+/// A live transaction for the same user, item and amount whose timestamp is
+/// within `window_secs` of `tx`; a window of 0 disables the check.
+fn find_duplicate<'a>(
+    ledger: &'a Ledger,
+    tx: &Transaction,
+    window_secs: u64,
+) -> Option<&'a Transaction> {
+    if window_secs == 0 {
+        return None;
+    }
+    ledger.for_user(&tx.user).find(|other| {
+        !other.deleted
+            && other.item == tx.item
+            && other.amount == tx.amount
+            && other.currency == tx.currency
+            && other.timestamp.abs_diff(tx.timestamp) <= window_secs
+    })
+}
+
+#[utoipa::path(
+    tag = "transactions",
+    params(CreateOptions, ("Idempotency-Key" = Option<String>, Header, description = "retries with the same key return the original transaction")),
+    request_body = CreateTransaction,
+    responses(
+        (status = 201, description = "created", body = Transaction),
+        (status = 200, description = "replayed Idempotency-Key", body = Transaction),
+        (status = 400, description = "validation failed"),
+        (status = 409, description = "likely duplicate of an existing transaction, or If-Match no longer matches the collection"),
+    )
+)]
+#[post("/transactions")]
+async fn create_transaction(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    options: web::Query<CreateOptions>,
+    payload: web::Json<CreateTransaction>,
+) -> impl Responder {
+    let idempotency_key = match req.headers().get(IDEMPOTENCY_KEY_HEADER) {
+        None => None,
+        Some(value) => match value.to_str().map(str::trim) {
+            Ok(key) if !key.is_empty() && key.len() <= idempotency::MAX_KEY_LEN => {
+                Some(key.to_string())
+            }
+            _ => {
+                return HttpResponse::BadRequest().json(serde_json::json!({
+                    "error": "Idempotency-Key must be 1-255 visible ASCII characters"
+                }));
+            }
+        },
+    };
+
+    if let Err(errors) = payload.validate() {
+        return validation_failed(errors);
+    }
+
+    let mut tx = payload.to_transaction();
+
+    {
+        // acquire write lock, mutate, then release before any await
+        let Some(mut write_guard) = state.write_ledger().await else {
+            return lock_timeout();
+        };
+        // checked under the write lock so concurrent retries can't both create
+        if let Some(original) = idempotency_key
+            .as_deref()
+            .and_then(|key| state.idempotency.get(key))
+            .and_then(|id| write_guard.get(&id))
+        {
+            return HttpResponse::Ok()
+                .insert_header((
+                    header::LOCATION,
+                    state.url(&format!("/transactions/{}", original.id)),
+                ))
+                .insert_header(("Idempotent-Replayed", "true"))
+                .json(original);
+        }
+        // after the replay, which answers a retry even though its own create moved the tag on
+        if let Some(stale) = stale_collection(&req, &write_guard) {
+            return stale;
+        }
+        if !options.force
+            && let Some(existing) =
+                find_duplicate(&write_guard, &tx, state.config.duplicate_window_secs)
+        {
+            return HttpResponse::Conflict().json(serde_json::json!({
+                "error": "a matching transaction was created moments ago; retry with ?force=true to keep both",
+                "existing": existing
+            }));
+        }
+        if let Some(key) = idempotency_key {
+            state.idempotency.insert(key, tx.id);
+        }
+        tx.seq = write_guard.next_seq();
+        write_guard.push(tx.clone());
+        state
+            .undo
+            .record(Step::new("create", Inverse::Remove(vec![tx.id])));
+    } // lock released here
+
+    // persist asynchronously
+    if let Err(e) = state.persist().await {
+        tracing::error!(error = %e, "Failed to persist transactions");
+        return persist_failed("failed to save transaction");
+    }
+    state.metrics.created.inc();
+    state.notify(ChangeKind::Created, &tx).await;
+
+    HttpResponse::Created()
+        .insert_header((
+            header::LOCATION,
+            state.url(&format!("/transactions/{}", tx.id)),
+        ))
+        .json(tx)
+}
+
+#[utoipa::path(
+    tag = "transactions",
+    request_body = Vec<CreateTransaction>,
+    responses(
+        (status = 201, description = "all created", body = Vec<Transaction>),
+        (status = 400, description = "at least one entry failed validation; nothing was created"),
+        (status = 409, description = "If-Match no longer matches the collection"),
+    )
+)]
+#[post("/transactions/batch")]
+async fn create_transactions_batch(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    payload: web::Json<Vec<CreateTransaction>>,
+) -> impl Responder {
+    if payload.is_empty() {
+        return HttpResponse::BadRequest()
+            .json(serde_json::json!({"error":"batch must contain at least one transaction"}));
+    }
+
+    // validate every entry up front so the batch is all-or-nothing
+    let failed: Vec<serde_json::Value> = payload
+        .iter()
+        .enumerate()
+        .filter_map(|(index, p)| {
+            p.validate()
+                .err()
+                .map(|errors| serde_json::json!({"index": index, "errors": errors}))
+        })
+        .collect();
+    if !failed.is_empty() {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "batch validation failed",
+            "failed": failed
+        }));
+    }
+
+    let mut created: Vec<Transaction> = payload.iter().map(|p| p.to_transaction()).collect();
+
+    {
+        let Some(mut write_guard) = state.write_ledger().await else {
+            return lock_timeout();
+        };
+        if let Some(stale) = stale_collection(&req, &write_guard) {
+            return stale;
+        }
+        write_guard.number(&mut created);
+        write_guard.extend(created.iter().cloned());
+        let ids = created.iter().map(|t| t.id).collect();
+        state.undo.record(Step::new("batch", Inverse::Remove(ids)));
+    }
+
+    if let Err(e) = state.persist().await {
+        tracing::error!(error = %e, "Failed to persist batch");
+        return persist_failed("failed to save transactions");
+    }
+    state.metrics.created.inc_by(created.len() as u64);
+    for tx in &created {
+        state.notify(ChangeKind::Created, tx).await;
+    }
+
+    HttpResponse::Created().json(created)
+}
+
+#[utoipa::path(
+    tag = "transactions",
+    params(ImportOptions),
+    request_body(content = String, content_type = "text/csv", description = "header row user,item,amount[,kind][,timestamp]; amounts are non-negative, with kind debit (default) or credit"),
+    responses(
+        (status = 200, description = "counts of imported and skipped rows with per-line errors"),
+        (status = 400, description = "not parseable as CSV"),
+        (status = 409, description = "If-Match no longer matches the collection"),
+        (status = 413, description = "body over the size limit"),
+    )
+)]
+#[post("/transactions/import")]
+async fn import_transactions(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    options: web::Query<ImportOptions>,
+    payload: web::Payload,
+) -> impl Responder {
+    let body = match payload.to_bytes_limited(state.config.max_body_bytes).await {
+        Ok(Ok(body)) => body,
+        Ok(Err(e)) => {
+            return HttpResponse::BadRequest().json(serde_json::json!({
+                "error": "failed to read request body",
+                "detail": e.to_string()
+            }));
+        }
+        Err(_) => return payload_too_large(state.config.max_body_bytes),
+    };
+    let (mut accepted, errors) = match parse_csv_import(&body) {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            return HttpResponse::BadRequest().json(serde_json::json!({
+                "error": "invalid CSV",
+                "detail": e.to_string()
+            }));
+        }
+    };
+    let imported = accepted.len();
+
+    // everything above is shared with the real run, so the preview can't drift from it
+    if imported > 0 && !options.dry_run {
+        {
+            // append the whole batch under one lock so the import is all-or-nothing in memory
+            let Some(mut write_guard) = state.write_ledger().await else {
+                return lock_timeout();
+            };
+            if let Some(stale) = stale_collection(&req, &write_guard) {
+                return stale;
+            }
+            write_guard.number(&mut accepted);
+            write_guard.extend(accepted.iter().cloned());
+            let ids = accepted.iter().map(|t| t.id).collect();
+            state.undo.record(Step::new("import", Inverse::Remove(ids)));
+        }
+
+        if let Err(e) = state.persist().await {
+            tracing::error!(error = %e, "Failed to persist after import");
+            return persist_failed("failed to save imported transactions");
+        }
+        state.metrics.created.inc_by(imported as u64);
+        for tx in &accepted {
+            state.notify(ChangeKind::Created, tx).await;
+        }
+    }
+
+    HttpResponse::Ok().json(serde_json::json!({
+        "imported": imported,
+        "skipped": errors.len(),
+        "errors": errors,
+        "dry_run": options.dry_run
+    }))
+}
+
+#[utoipa::path(
+    tag = "transactions",
+    params(Pagination, Sorting, TransactionFilter),
+    responses(
+        (status = 200, description = "`{ total, items }` for the requested page; the ETag identifies the whole collection's current revision",
+            headers(
+                ("X-Total-Count" = usize, description = "rows matching the filter"),
+                ("X-Page-Limit" = usize, description = "page size used"),
+                ("X-Page-Offset" = usize, description = "position of the first row returned"),
+                ("Link" = String, description = "`rel=\"next\"` and `rel=\"prev\"` page URLs, when there are such pages"),
+            )
+        ),
+        (status = 400, description = "invalid query parameters"),
+    )
+)]
+#[get("/transactions")]
+async fn list_transactions(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    page: web::Query<Pagination>,
+    sorting: web::Query<Sorting>,
+    filter: web::Query<TransactionFilter>,
+) -> impl Responder {
+    if let Err(msg) = filter.validate() {
+        return HttpResponse::BadRequest().json(serde_json::json!({ "error": msg }));
+    }
+    let limit = page.limit.unwrap_or(DEFAULT_PAGE_LIMIT);
+    let by_timestamp = matches!(sorting.sort.unwrap_or_default(), SortKey::Timestamp);
+    let after = match page.after.as_deref().map(Uuid::parse_str) {
+        None => None,
+        Some(Err(_)) => {
+            return HttpResponse::BadRequest()
+                .json(serde_json::json!({"error":"after must be a transaction id"}));
+        }
+        Some(Ok(_)) if page.offset.is_some() => {
+            return HttpResponse::BadRequest()
+                .json(serde_json::json!({"error":"use either after or offset, not both"}));
+        }
+        Some(Ok(_)) if !by_timestamp => {
+            return HttpResponse::BadRequest()
+                .json(serde_json::json!({"error":"after requires sorting by timestamp"}));
+        }
+        Some(Ok(id)) => Some(id),
+    };
+
+    let Some(read_guard) = state.read_ledger().await else {
+        return lock_timeout();
+    };
+    let mut matching: Vec<&Transaction> = filter.apply_filter(&read_guard).collect();
+    // sort the snapshot of references, never the stored vector
+    sorting.sort(&mut matching);
+    let total = matching.len();
+    let start = match after {
+        // the cursor row needn't match the filter (or even be live), it only marks a position
+        Some(id) => match read_guard.get(&id) {
+            Some(cursor) => {
+                let key = (cursor.timestamp, cursor.id);
+                let ascending = matches!(sorting.order.unwrap_or_default(), SortOrder::Asc);
+                matching.partition_point(|t| {
+                    let k = (t.timestamp, t.id);
+                    if ascending { k <= key } else { k >= key }
+                })
+            }
+            None => {
+                return HttpResponse::BadRequest()
+                    .json(serde_json::json!({"error":"unknown cursor"}));
+            }
+        },
+        // clamp both ends so an offset past the end yields an empty page
+        None => page.offset.unwrap_or(0).min(total),
+    };
+    let end = start.saturating_add(limit).min(total);
+    let next_cursor = (by_timestamp && end < total)
+        .then(|| matching[start..end].last().map(|t| t.id))
+        .flatten();
+    // copy out just this page so the lock isn't held while a slow client reads
+    let items: Vec<Transaction> = matching[start..end].iter().map(|&t| t.clone()).collect();
+    let etag = collection_etag(&read_guard);
+    drop(read_guard);
+
+    let mut response = HttpResponse::Ok();
+    response
+        .content_type("application/json")
+        .insert_header(header::ETag(etag))
+        .insert_header(("X-Total-Count", total))
+        .insert_header(("X-Page-Limit", limit))
+        .insert_header(("X-Page-Offset", start));
+    // a cursor walk continues by cursor; there is no cursor for the page before
+    let next = match after {
+        Some(_) => next_cursor.map(|id| ("after", id.to_string())),
+        None => (end < total).then(|| ("offset", end.to_string())),
+    };
+    let prev =
+        (after.is_none() && start > 0).then(|| ("offset", start.saturating_sub(limit).to_string()));
+    let links: Vec<String> = [(next, "next"), (prev, "prev")]
+        .into_iter()
+        .filter_map(|(to, rel)| to.map(|to| format!("<{}>; rel=\"{}\"", page_url(&req, to), rel)))
+        .collect();
+    if !links.is_empty() {
+        response.insert_header((header::LINK, links.join(", ")));
+    }
+    response.streaming(streaming::json_array_body(
+        format!(
+            "{{\"total\":{},\"next_cursor\":{},\"items\":",
+            total,
+            serde_json::json!(next_cursor)
+        ),
+        items,
+        "}",
+    ))
+}
+
+/// The n most recent transactions, newest first.
+#[utoipa::path(
+    tag = "transactions",
+    params(TopQuery, DeletedFilter),
+    responses((status = 200, body = Vec<Transaction>))
+)]
+#[get("/transactions/latest")]
+async fn latest_transactions(
+    state: web::Data<AppState>,
+    query: web::Query<TopQuery>,
+    deleted: web::Query<DeletedFilter>,
+) -> impl Responder {
+    let n = query.n.unwrap_or(DEFAULT_TOP_N);
+    let Some(read_guard) = state.read_ledger().await else {
+        return lock_timeout();
+    };
+    let mut recent: Vec<&Transaction> = read_guard.iter().filter(|t| deleted.allows(t)).collect();
+    // newest first, ties broken by id as in the list endpoint
+    let newest_first =
+        |a: &&Transaction, b: &&Transaction| (b.timestamp, b.id).cmp(&(a.timestamp, a.id));
+    if n < recent.len() {
+        // only the first n need to end up in order
+        recent.select_nth_unstable_by(n, newest_first);
+        recent.truncate(n);
+    }
+    recent.sort_unstable_by(newest_first);
+    HttpResponse::Ok().json(recent)
+}
+
+#[utoipa::path(
+    tag = "transactions",
+    params(TransactionFilter),
+    responses((status = 200, description = "`{ count }`"), (status = 400, description = "invalid query parameters"))
+)]
+#[get("/transactions/count")]
+async fn count_transactions(
+    state: web::Data<AppState>,
+    filter: web::Query<TransactionFilter>,
+) -> impl Responder {
+    if let Err(msg) = filter.validate() {
+        return HttpResponse::BadRequest().json(serde_json::json!({ "error": msg }));
+    }
+    let Some(read_guard) = state.read_ledger().await else {
+        return lock_timeout();
+    };
+    let count = filter.apply_filter(&read_guard).count();
+    HttpResponse::Ok().json(serde_json::json!({ "count": count }))
+}
+
+#[utoipa::path(
+    tag = "transactions",
+    params(SearchQuery, DeletedFilter),
+    responses((status = 200, description = "matches on item or user", body = Vec<Transaction>), (status = 400, description = "missing q"))
+)]
+#[get("/transactions/search")]
+async fn search_transactions(
+    state: web::Data<AppState>,
+    query: web::Query<SearchQuery>,
+    deleted: web::Query<DeletedFilter>,
+) -> impl Responder {
+    let needle = match query.q.as_deref().map(str::trim) {
+        Some(q) if !q.is_empty() => q.to_lowercase(),
+        _ => {
+            return HttpResponse::BadRequest()
+                .json(serde_json::json!({"error":"q must be a non-empty string"}));
+        }
+    };
+
+    let Some(read_guard) = state.read_ledger().await else {
+        return lock_timeout();
+    };
+    let matches: Vec<&Transaction> = read_guard
+        .iter()
+        .filter(|t| deleted.allows(t) && matches_search(t, &needle))
+        .collect();
+    HttpResponse::Ok().json(matches)
+}
+
+/// Render transactions as CSV, always emitting the header row even when empty.
+fn transactions_to_csv<'a>(
+    txs: impl IntoIterator<Item = &'a Transaction>,
+) -> Result<Vec<u8>, csv::Error> {
+    let mut writer = csv::WriterBuilder::new()
+        .has_headers(false)
+        .from_writer(Vec::new());
+    writer.write_record(["id", "user", "item", "amount", "timestamp"])?;
+    for t in txs {
+        writer.serialize(CsvExportRow {
+            id: t.id,
+            user: &t.user,
+            item: &t.item,
+            amount: t.amount,
+            timestamp: t.timestamp,
+        })?;
+    }
+    writer.into_inner().map_err(|e| e.into_error().into())
+}
+
+/// `transactions-alice-from-2024-01-01-to-2024-01-31.csv` and the like, so
+/// scoped exports don't all land under the same name.
+fn export_filename(filter: &TransactionFilter, extension: &str) -> String {
+    let date = |ts: u64| {
+        i64::try_from(ts)
+            .ok()
+            .and_then(|ts| DateTime::<Utc>::from_timestamp(ts, 0))
+            .map(|dt| dt.format("%Y-%m-%d").to_string())
+    };
+    let mut name = "transactions".to_string();
+    if let Some(user) = filter
+        .user
+        .as_deref()
+        .map(str::trim)
+        .filter(|u| !u.is_empty())
+    {
+        // the header value is quoted, so keep the name to characters that need no escaping
+        let safe: String = user
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() || c == '-' {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect();
+        name.push('-');
+        name.push_str(&safe);
+    }
+    if let Some(from) = filter.from.and_then(date) {
+        name.push_str("-from-");
+        name.push_str(&from);
+    }
+    if let Some(to) = filter.to.and_then(date) {
+        name.push_str("-to-");
+        name.push_str(&to);
+    }
+    format!("{}.{}", name, extension)
+}
+
+#[utoipa::path(
+    tag = "transactions",
+    params(TransactionFilter),
+    responses(
+        (status = 200, description = "CSV download of the transactions the filters select; \
+            the filename names the user and date range", content_type = "text/csv", body = String),
+        (status = 400, description = "invalid filter")
+    )
+)]
+#[get("/transactions/export.csv")]
+async fn export_csv(
+    state: web::Data<AppState>,
+    filter: web::Query<TransactionFilter>,
+) -> impl Responder {
+    if let Err(msg) = filter.validate() {
+        return HttpResponse::BadRequest().json(serde_json::json!({ "error": msg }));
+    }
+    let body = {
+        let Some(read_guard) = state.read_ledger().await else {
+            return lock_timeout();
+        };
+        match transactions_to_csv(filter.apply_filter(&read_guard)) {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                tracing::error!(error = %e, "Failed to write CSV export");
+                return HttpResponse::InternalServerError()
+                    .json(serde_json::json!({"error":"failed to export transactions"}));
+            }
+        }
+    };
+
+    HttpResponse::Ok()
+        .content_type("text/csv")
+        .insert_header((
+            "Content-Disposition",
+            format!(
+                "attachment; filename=\"{}\"",
+                export_filename(&filter, "csv")
+            ),
+        ))
+        .body(body)
+}
+
+#[utoipa::path(
+    tag = "transactions",
+    responses((status = 200, description = "JSON download of every transaction, soft-deleted \
+        ones included, in the same format as the storage file", body = [Transaction]))
+)]
+#[get("/transactions/export.json")]
+async fn export_json(state: web::Data<AppState>) -> impl Responder {
+    let Some(read_guard) = state.read_ledger().await else {
+        return lock_timeout();
+    };
+    // rows added after this point are left out, so the file is the ledger as of now
+    let (end, layout) = (read_guard.len(), read_guard.layout());
+    drop(read_guard);
+    let fetch = move |start: usize| {
+        let state = state.clone();
+        async move {
+            let read_guard = state.read_ledger().await.ok_or_else(lock_timed_out)?;
+            if read_guard.layout() != layout {
+                tracing::warn!("Ledger rows moved during a JSON export; ending it early");
+                return Err(std::io::Error::other(
+                    "the ledger changed during the export",
+                ));
+            }
+            let stop = start.saturating_add(streaming::ITEMS_PER_CHUNK).min(end);
+            Ok((read_guard[start..stop].to_vec(), stop))
+        }
+    };
+    let today = Utc::now().format("%Y-%m-%d");
+    HttpResponse::Ok()
+        .content_type("application/json")
+        .insert_header((
+            "Content-Disposition",
+            format!("attachment; filename=\"transactions-{}.json\"", today),
+        ))
+        .streaming(streaming::json_array_pages(String::new(), fetch, ""))
+}
+
+#[utoipa::path(
+    tag = "transactions",
+    params(("id" = Uuid, Path, description = "transaction id")),
+    responses((status = 200, body = Transaction), (status = 400, description = "invalid uuid"), (status = 404, description = "not found"))
+)]
+#[get("/transactions/{id}")]
+async fn get_transaction(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    path: web::Path<String>,
+) -> impl Responder {
+    let id_str = path.into_inner();
+    let id = match Uuid::parse_str(&id_str) {
+        Ok(u) => u,
+        Err(_) => {
+            return HttpResponse::BadRequest().json(serde_json::json!({"error":"invalid uuid"}));
+        }
+    };
+
+    let Some(read_guard) = state.read_ledger().await else {
+        return lock_timeout();
+    };
+    match read_guard.get(&id) {
+        Some(tx) => single_transaction(&req, tx),
+        None => HttpResponse::NotFound().json(serde_json::json!({"error":"not found"})),
+    }
+}
+
+#[utoipa::path(
+    tag = "transactions",
+    params(("n" = u64, Path, description = "sequence number")),
+    responses((status = 200, body = Transaction), (status = 404, description = "not found"))
+)]
+#[get("/transactions/by-seq/{n}")]
+async fn get_transaction_by_seq(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    path: web::Path<u64>,
+) -> impl Responder {
+    let Some(read_guard) = state.read_ledger().await else {
+        return lock_timeout();
+    };
+    match read_guard.get_by_seq(path.into_inner()) {
+        Some(tx) => single_transaction(&req, tx),
+        None => HttpResponse::NotFound().json(serde_json::json!({"error":"not found"})),
+    }
+}
+
+/// 200 with an ETag, or 304 when the client's If-None-Match already has it.
+fn single_transaction(req: &HttpRequest, tx: &Transaction) -> HttpResponse {
+    let etag = transaction_etag(tx);
+    // a weak comparison, as RFC 9110 prescribes for If-None-Match
+    let unchanged = match header::IfNoneMatch::parse(req) {
+        Ok(header::IfNoneMatch::Any) => true,
+        Ok(header::IfNoneMatch::Items(tags)) => tags.iter().any(|t| t.weak_eq(&etag)),
+        Err(_) => false,
+    };
+    if unchanged {
+        return HttpResponse::NotModified()
+            .insert_header(header::ETag(etag))
+            .finish();
+    }
+    HttpResponse::Ok()
+        .insert_header(header::ETag(etag))
+        .json(tx)
+}
+
+/// Validate every field present in `patch` and apply them to `tx`, collecting all
+/// failures. Callers apply this to a copy so a rejected patch never leaves partial
+/// edits behind.
+fn apply_patch(tx: &mut Transaction, patch: &UpdateTransaction) -> Result<(), Vec<FieldError>> {
+    let mut errors = Vec::new();
+    if let Some(user) = &patch.user {
+        if user.trim().is_empty() {
+            errors.push(FieldError::new("user", "cannot be empty"));
+        }
+        tx.user = user.trim().to_string();
+    }
+    if let Some(item) = &patch.item {
+        if item.trim().is_empty() {
+            errors.push(FieldError::new("item", "cannot be empty"));
+        }
+        tx.item = item.trim().to_string();
+    }
+    if let Some(category) = &patch.category {
+        if category.trim().is_empty() {
+            errors.push(FieldError::new("category", "cannot be empty"));
+        }
+        tx.category = Some(category.trim().to_string());
+    }
+    if let Some(currency) = &patch.currency {
+        if !is_valid_currency(currency) {
+            errors.push(FieldError::new("currency", CURRENCY_ERROR));
+        }
+        tx.currency = currency.clone();
+    }
+    if patch.amount.is_some() || patch.kind.is_some() {
+        let magnitude = patch.amount.unwrap_or(tx.amount.abs());
+        if magnitude.is_sign_negative() {
+            errors.push(FieldError::new("amount", NEGATIVE_AMOUNT_ERROR));
+        }
+        let kind = patch.kind.unwrap_or(EntryKind::of(tx.amount));
+        tx.amount = kind.signed(magnitude);
+    }
+    if patch.amount.is_some() || patch.kind.is_some() || patch.currency.is_some() {
+        tx.amount = round_amount(tx.amount, &tx.currency);
+    }
+    if let Some(ts) = patch.timestamp {
+        tx.timestamp = ts;
+    }
+    if let Some(note) = &patch.note {
+        match normalize_note(note) {
+            Ok(note) => tx.note = note,
+            Err(msg) => errors.push(FieldError::new("note", msg)),
+        }
+    }
+    if let Some(tags) = &patch.tags {
+        match normalize_tags(tags) {
+            Ok(tags) => tx.tags = tags,
+            Err(msg) => errors.push(FieldError::new("tags", msg)),
+        }
+    }
+    if let Some(url) = &patch.attachment_url {
+        match normalize_attachment_url(url) {
+            Ok(url) => tx.attachment_url = url,
+            Err(msg) => errors.push(FieldError::new("attachment_url", msg)),
+        }
+    }
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+/// Entity tag for a single transaction. The version changes on every mutation,
+/// so it identifies a representation without hashing the body, and it's the
+/// same value `If-Match` expects on PUT and PATCH.
+fn transaction_etag(tx: &Transaction) -> header::EntityTag {
+    header::EntityTag::new_strong(tx.version.to_string())
+}
+
+/// This request's URL with `offset` and `after` replaced by `position`, for
+/// the `Link` header. Every other query parameter is kept as sent.
+fn page_url(req: &HttpRequest, position: (&str, String)) -> String {
+    let mut query = url::form_urlencoded::Serializer::new(String::new());
+    for (key, value) in url::form_urlencoded::parse(req.query_string().as_bytes()) {
+        if key != "offset" && key != "after" {
+            query.append_pair(&key, &value);
+        }
+    }
+    query.append_pair(position.0, &position.1);
+    format!("{}?{}", req.path(), query.finish())
+}
+
+/// Entity tag for the whole collection, sent on `GET /transactions`. It
+/// changes with every successful mutation and with every restart.
+fn collection_etag(ledger: &Ledger) -> header::EntityTag {
+    header::EntityTag::new_strong(ledger.revision_tag())
+}
+
+/// Writes that add to or remove from the collection may send back the tag
+/// from `GET /transactions` in `If-Match` to make sure nothing has changed
+/// since; a 409 carrying the current tag when something has. `*` or no
+/// header skips the check. Call with the write lock held.
+fn stale_collection(req: &HttpRequest, ledger: &Ledger) -> Option<HttpResponse> {
+    let raw = req.headers().get(header::IF_MATCH)?.to_str().unwrap_or("");
+    let current = collection_etag(ledger);
+    let matches = raw.split(',').map(str::trim).any(|tag| {
+        tag == "*" || tag.strip_prefix("W/").unwrap_or(tag).trim_matches('"') == current.tag()
+    });
+    if matches {
+        return None;
+    }
+    Some(
+        HttpResponse::Conflict()
+            .insert_header((header::ETAG, current.clone()))
+            .json(serde_json::json!({
+                "error": "the transaction list has changed; fetch it again and retry",
+                "current": current.to_string()
+            })),
+    )
+}
+
+/// Version the client expects to be editing, from `If-Match` and/or the body.
+/// `If-Match: *` matches any version; both sources must agree when both are sent.
+fn expected_version(
+    req: &HttpRequest,
+    body_version: Option<u64>,
+) -> Result<Option<u64>, Box<HttpResponse>> {
+    let header_version = match req.headers().get(header::IF_MATCH) {
+        None => None,
+        Some(value) => {
+            let raw = value.to_str().unwrap_or("").trim();
+            if raw == "*" {
+                None
+            } else {
+                let tag = raw.strip_prefix("W/").unwrap_or(raw).trim_matches('"');
+                match tag.parse::<u64>() {
+                    Ok(v) => Some(v),
+                    Err(_) => {
+                        return Err(Box::new(HttpResponse::BadRequest().json(
+                            serde_json::json!({"error": "If-Match must be a transaction version"}),
+                        )));
+                    }
+                }
+            }
+        }
+    };
+    match (header_version, body_version) {
+        (Some(h), Some(b)) if h != b => Err(Box::new(
+            HttpResponse::BadRequest()
+                .json(serde_json::json!({"error": "If-Match header and body version disagree"})),
+        )),
+        (h, b) => Ok(h.or(b)),
+    }
+}
+
+/// 409 when the client expected a different version of `tx` than the stored one.
+fn stale_version(expected: Option<u64>, tx: &Transaction) -> Option<HttpResponse> {
+    let expected = expected.filter(|&v| v != tx.version)?;
+    Some(HttpResponse::Conflict().json(serde_json::json!({
+        "error": "version mismatch",
+        "expected": expected,
+        "current": tx.version
+    })))
+}
+
+/// Shared body of PUT and PATCH: look up a live transaction, check the expected
+/// version, let `edit` build its replacement, store it and persist. Error
+/// responses are boxed to keep the closure's `Result` small.
+async fn edit_transaction(
+    state: &AppState,
+    id_str: &str,
+    expected_version: Option<u64>,
+    edit: impl FnOnce(&Transaction) -> Result<Transaction, Box<HttpResponse>>,
+) -> HttpResponse {
+    let id = match Uuid::parse_str(id_str) {
+        Ok(u) => u,
+        Err(_) => {
+            return HttpResponse::BadRequest().json(serde_json::json!({"error":"invalid uuid"}));
+        }
+    };
+
+    let outcome = {
+        let Some(mut write_guard) = state.write_ledger().await else {
+            return lock_timeout();
+        };
+        write_guard.update(&id, |tx| {
+            if tx.locked {
+                return Err(Box::new(locked_response(tx.id)));
+            }
+            if tx.deleted {
+                return Err(Box::new(HttpResponse::Conflict().json(serde_json::json!({
+                    "error": "transaction is deleted; restore it before editing"
+                }))));
+            }
+            if let Some(stale) = stale_version(expected_version, tx) {
+                return Err(Box::new(stale));
+            }
+            let before = tx.clone();
+            *tx = edit(tx)?;
+            tx.bump_version();
+            state
+                .undo
+                .record(Step::new("update", Inverse::Revert(vec![before])));
+            Ok(tx.clone())
+        })
+    }; // lock released before await
+    let updated = match outcome {
+        Some(Ok(updated)) => updated,
+        Some(Err(response)) => return *response,
+        None => return HttpResponse::NotFound().json(serde_json::json!({"error":"not found"})),
+    };
+
+    if let Err(e) = state.persist().await {
+        tracing::error!(error = %e, "Failed to persist after update");
+        return persist_failed("failed to save changes");
+    }
+    state.metrics.updated.inc();
+    state.notify(ChangeKind::Updated, &updated).await;
+
+    HttpResponse::Ok()
+        .insert_header(header::ETag(transaction_etag(&updated)))
+        .json(updated)
+}
+
+/// Full replacement: user, item, amount and timestamp are required, and optional
+/// fields left out are reset to their defaults.
+#[utoipa::path(
+    tag = "transactions",
+    params(("id" = Uuid, Path, description = "transaction id"), ("If-Match" = Option<String>, Header, description = "expected version")),
+    request_body = UpdateTransaction,
+    responses(
+        (status = 200, body = Transaction),
+        (status = 400, description = "validation failed"),
+        (status = 404, description = "not found"),
+        (status = 409, description = "deleted, or version mismatch"),
+        (status = 423, description = "locked by a period close"),
+    )
+)]
+#[put("/transactions/{id}")]
+async fn update_transaction(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    path: web::Path<String>,
+    payload: web::Json<UpdateTransaction>,
+) -> impl Responder {
+    let expected = match expected_version(&req, payload.version) {
+        Ok(v) => v,
+        Err(response) => return *response,
+    };
+    let missing: Vec<FieldError> = [
+        ("user", payload.user.is_none()),
+        ("item", payload.item.is_none()),
+        ("amount", payload.amount.is_none()),
+        ("timestamp", payload.timestamp.is_none()),
+    ]
+    .into_iter()
+    .filter(|&(_, absent)| absent)
+    .map(|(field, _)| FieldError::new(field, "required for PUT; use PATCH for partial updates"))
+    .collect();
+    if !missing.is_empty() {
+        return validation_failed(missing);
+    }
+
+    edit_transaction(&state, &path, expected, |current| {
+        let mut replacement = Transaction {
+            id: current.id,
+            seq: current.seq,
+            user: String::new(),
+            item: String::new(),
+            amount: Decimal::ZERO,
+            timestamp: 0,
+            category: None,
+            currency: default_currency(),
+            deleted: current.deleted,
+            version: current.version,
+            note: None,
+            tags: Vec::new(),
+            transfer_id: current.transfer_id,
+            attachment_url: None,
+            // uploaded separately, so a full replacement keeps it
+            receipt: current.receipt.clone(),
+            locked: current.locked,
+        };
+        apply_patch(&mut replacement, &payload).map_err(|e| Box::new(validation_failed(e)))?;
+        Ok(replacement)
+    })
+    .await
+}
+
+/// Partial update: only the fields present in the body change.
+#[utoipa::path(
+    tag = "transactions",
+    params(("id" = Uuid, Path, description = "transaction id"), ("If-Match" = Option<String>, Header, description = "expected version")),
+    request_body = UpdateTransaction,
+    responses(
+        (status = 200, body = Transaction),
+        (status = 400, description = "validation failed"),
+        (status = 404, description = "not found"),
+        (status = 409, description = "deleted, or version mismatch"),
+        (status = 423, description = "locked by a period close"),
+    )
+)]
+#[patch("/transactions/{id}")]
+async fn patch_transaction(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    path: web::Path<String>,
+    payload: web::Json<UpdateTransaction>,
+) -> impl Responder {
+    let expected = match expected_version(&req, payload.version) {
+        Ok(v) => v,
+        Err(response) => return *response,
+    };
+    edit_transaction(&state, &path, expected, |current| {
+        let mut patched = current.clone();
+        apply_patch(&mut patched, &payload).map_err(|e| Box::new(validation_failed(e)))?;
+        Ok(patched)
+    })
+    .await
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct DeleteOptions {
+    /// also delete the other leg of a transfer
+    #[serde(default)]
+    pub with_pair: bool,
+}
+
+#[utoipa::path(
+    tag = "transactions",
+    params(("id" = Uuid, Path, description = "transaction id"), ("If-Match" = Option<String>, Header, description = "expected version"), DeleteOptions),
+    responses(
+        (status = 204, description = "soft-deleted"),
+        (status = 404, description = "not found"),
+        (status = 409, description = "version mismatch"),
+        (status = 423, description = "locked by a period close"),
+    )
+)]
+#[delete("/transactions/{id}")]
+async fn delete_transaction(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    path: web::Path<String>,
+    options: web::Query<DeleteOptions>,
+) -> impl Responder {
+    let id_str = path.into_inner();
+    let id = match Uuid::parse_str(&id_str) {
+        Ok(u) => u,
+        Err(_) => {
+            return HttpResponse::BadRequest().json(serde_json::json!({"error":"invalid uuid"}));
+        }
+    };
+    let expected = match expected_version(&req, None) {
+        Ok(v) => v,
+        Err(response) => return *response,
+    };
+
+    let deleted = {
+        // soft delete: the row is only flagged, so history is preserved
+        let Some(mut write_guard) = state.write_ledger().await else {
+            return lock_timeout();
+        };
+        let ids: Vec<Uuid> = match write_guard.get(&id) {
+            Some(tx) if !tx.deleted => {
+                // the version is the addressed leg's; its pair goes with it
+                if let Some(stale) = stale_version(expected, tx) {
+                    return stale;
+                }
+                let mut ids = vec![id];
+                if let Some(pair) = tx.transfer_id.filter(|_| options.with_pair) {
+                    ids.extend(
+                        write_guard
+                            .iter()
+                            .filter(|t| t.transfer_id == Some(pair) && t.id != id && !t.deleted)
+                            .map(|t| t.id),
+                    );
+                }
+                ids
+            }
+            _ => return HttpResponse::NotFound().json(serde_json::json!({"error":"not found"})),
+        };
+        // one locked leg keeps the whole pair in place
+        if let Some(locked) = ids
+            .iter()
+            .filter_map(|id| write_guard.get(id))
+            .find(|t| t.locked)
+        {
+            return locked_response(locked.id);
+        }
+        let mut before = Vec::with_capacity(ids.len());
+        let mut deleted = Vec::with_capacity(ids.len());
+        for id in &ids {
+            write_guard.update(id, |tx| {
+                before.push(tx.clone());
+                tx.deleted = true;
+                tx.bump_version();
+                deleted.push(tx.clone());
+            });
+        }
+        state
+            .undo
+            .record(Step::new("delete", Inverse::Revert(before)));
+        deleted
+    };
+
+    if let Err(e) = state.persist().await {
+        tracing::error!(error = %e, "Failed to persist after delete");
+        return persist_failed("failed to persist delete");
+    }
+    state.metrics.deleted.inc_by(deleted.len() as u64);
+    for tx in &deleted {
+        state.notify(ChangeKind::Deleted, tx).await;
+    }
+
+    HttpResponse::NoContent().finish()
+}
+
+#[utoipa::path(
+    tag = "transactions",
+    params(("id" = Uuid, Path, description = "transaction id"), ("If-Match" = Option<String>, Header, description = "expected version")),
+    responses(
+        (status = 200, body = Transaction),
+        (status = 404, description = "not found or not deleted"),
+        (status = 409, description = "not deleted, or version mismatch"),
+        (status = 423, description = "locked by a period close"),
+    )
+)]
+#[post("/transactions/{id}/restore")]
+async fn restore_transaction(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    path: web::Path<String>,
+) -> impl Responder {
+    let id_str = path.into_inner();
+    let id = match Uuid::parse_str(&id_str) {
+        Ok(u) => u,
+        Err(_) => {
+            return HttpResponse::BadRequest().json(serde_json::json!({"error":"invalid uuid"}));
+        }
+    };
+    let expected = match expected_version(&req, None) {
+        Ok(v) => v,
+        Err(response) => return *response,
+    };
+
+    let restored = {
+        let Some(mut write_guard) = state.write_ledger().await else {
+            return lock_timeout();
+        };
+        let outcome = write_guard.update(&id, |tx| {
+            if tx.locked {
+                return Some(Err(locked_response(tx.id)));
+            }
+            if !tx.deleted {
+                return None;
+            }
+            if let Some(stale) = stale_version(expected, tx) {
+                return Some(Err(stale));
+            }
+            let before = tx.clone();
+            tx.deleted = false;
+            tx.bump_version();
+            state
+                .undo
+                .record(Step::new("restore", Inverse::Revert(vec![before])));
+            Some(Ok(tx.clone()))
+        });
+        match outcome {
+            Some(Some(Ok(restored))) => restored,
+            Some(Some(Err(response))) => return response,
+            Some(None) => {
+                return HttpResponse::Conflict()
+                    .json(serde_json::json!({"error":"transaction is not deleted"}));
+            }
+            None => {
+                return HttpResponse::NotFound().json(serde_json::json!({"error":"not found"}));
+            }
+        }
+    };
+
+    if let Err(e) = state.persist().await {
+        tracing::error!(error = %e, "Failed to persist after restore");
+        return persist_failed("failed to persist restore");
+    }
+    state.notify(ChangeKind::Restored, &restored).await;
+
+    HttpResponse::Ok()
+        .insert_header(header::ETag(transaction_etag(&restored)))
+        .json(restored)
+}
+
+/// Every recorded change to one transaction, oldest first, with the state
+/// before and after each. Changes made before the audit log existed are not
+/// included.
+#[utoipa::path(
+    tag = "transactions",
+    params(("id" = Uuid, Path, description = "transaction id")),
+    responses(
+        (status = 200, description = "`{ transaction_id, total, items }`", body = Vec<AuditEntry>),
+        (status = 404, description = "no such transaction and no recorded history"),
+    )
+)]
+#[get("/transactions/{id}/history")]
+async fn transaction_history(
+    state: web::Data<AppState>,
+    path: web::Path<String>,
+) -> impl Responder {
+    let Ok(id) = Uuid::parse_str(&path) else {
+        return HttpResponse::BadRequest().json(serde_json::json!({"error":"invalid uuid"}));
+    };
+    let items = state.audit.history(&id).await;
+    // a removed transaction still has history worth showing
+    if items.is_empty() {
+        let Some(read_guard) = state.read_ledger().await else {
+            return lock_timeout();
+        };
+        if read_guard.get(&id).is_none() {
+            return HttpResponse::NotFound().json(serde_json::json!({"error":"not found"}));
+        }
+    }
+    HttpResponse::Ok().json(serde_json::json!({
+        "transaction_id": id,
+        "total": items.len(),
+        "items": items
+    }))
+}
+
+/// Copy a transaction under a new id and the current time. An optional JSON
+/// body overrides fields with the same rules as PATCH.
+#[utoipa::path(
+    tag = "transactions",
+    params(("id" = Uuid, Path, description = "transaction to copy"), ("If-Match" = Option<String>, Header, description = "expected version of the source")),
+    request_body(content = Option<UpdateTransaction>, description = "fields to change on the copy"),
+    responses(
+        (status = 201, description = "the new transaction", body = Transaction),
+        (status = 400, description = "invalid body or overrides"),
+        (status = 404, description = "not found"),
+        (status = 409, description = "source is deleted, or version mismatch"),
+    )
+)]
+#[post("/transactions/{id}/clone")]
+async fn clone_transaction(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    path: web::Path<String>,
+    body: web::Bytes,
+) -> impl Responder {
+    let Ok(id) = Uuid::parse_str(&path) else {
+        return HttpResponse::BadRequest().json(serde_json::json!({"error":"invalid uuid"}));
+    };
+    let expected = match expected_version(&req, None) {
+        Ok(v) => v,
+        Err(response) => return *response,
+    };
+    // the body is optional, so it can't go through the Json extractor
+    let overrides: UpdateTransaction = if body.iter().all(u8::is_ascii_whitespace) {
+        UpdateTransaction::default()
+    } else {
+        match serde_json::from_slice(&body) {
+            Ok(overrides) => overrides,
+            Err(e) => {
+                return HttpResponse::BadRequest().json(serde_json::json!({
+                    "error": "invalid JSON",
+                    "detail": e.to_string()
+                }));
+            }
+        }
+    };
+
+    let copy = {
+        let Some(mut write_guard) = state.write_ledger().await else {
+            return lock_timeout();
+        };
+        let Some(source) = write_guard.get(&id) else {
+            return HttpResponse::NotFound().json(serde_json::json!({"error":"not found"}));
+        };
+        if source.deleted {
+            return HttpResponse::Conflict().json(serde_json::json!({
+                "error": "transaction is deleted; restore it before cloning"
+            }));
+        }
+        if let Some(stale) = stale_version(expected, source) {
+            return stale;
+        }
+        let mut copy = Transaction {
+            id: Uuid::new_v4(),
+            timestamp: now_secs(),
+            version: initial_version(),
+            // a copy is a plain entry, not a third leg
+            transfer_id: None,
+            // the file belongs to the source's receipt folder
+            receipt: None,
+            // the copy is a new entry, outside whatever closed the source
+            locked: false,
+            ..source.clone()
+        };
+        if let Err(errors) = apply_patch(&mut copy, &overrides) {
+            return validation_failed(errors);
+        }
+        copy.seq = write_guard.next_seq();
+        write_guard.push(copy.clone());
+        state
+            .undo
+            .record(Step::new("clone", Inverse::Remove(vec![copy.id])));
+        copy
+    };
+
+    if let Err(e) = state.persist().await {
+        tracing::error!(error = %e, "Failed to persist clone");
+        return persist_failed("failed to save transaction");
+    }
+    state.metrics.created.inc();
+    state.notify(ChangeKind::Created, &copy).await;
+
+    HttpResponse::Created()
+        .insert_header((
+            header::LOCATION,
+            state.url(&format!("/transactions/{}", copy.id)),
+        ))
+        .json(copy)
+}
+
+/// Attach a receipt image or PDF, sent as the `file` field of a multipart form.
+/// A new upload replaces the previous one.
+#[utoipa::path(
+    tag = "transactions",
+    params(("id" = Uuid, Path, description = "transaction id")),
+    request_body(content = String, content_type = "multipart/form-data", description = "a `file` field holding a JPEG, PNG, GIF, WebP or PDF"),
+    responses(
+        (status = 200, description = "the transaction with its `receipt` set", body = Transaction),
+        (status = 400, description = "invalid uuid or no file field"),
+        (status = 404, description = "not found"),
+        (status = 409, description = "transaction is deleted"),
+        (status = 413, description = "file too large"),
+        (status = 415, description = "file type not allowed"),
+        (status = 423, description = "locked by a period close"),
+    )
+)]
+#[post("/transactions/{id}/receipt")]
+async fn upload_receipt(
+    state: web::Data<AppState>,
+    path: web::Path<String>,
+    mut form: actix_multipart::Multipart,
+) -> impl Responder {
+    let id = match Uuid::parse_str(&path) {
+        Ok(u) => u,
+        Err(_) => {
+            return HttpResponse::BadRequest().json(serde_json::json!({"error":"invalid uuid"}));
+        }
+    };
+    // refuse before reading the upload when it could never be attached
+    let Some(read_guard) = state.read_ledger().await else {
+        return lock_timeout();
+    };
+    match read_guard.get(&id) {
+        None => return HttpResponse::NotFound().json(serde_json::json!({"error":"not found"})),
+        Some(tx) if tx.locked => return locked_response(tx.id),
+        Some(tx) if tx.deleted => {
+            return HttpResponse::Conflict()
+                .json(serde_json::json!({"error":"transaction is deleted"}));
+        }
+        Some(_) => {}
+    }
+    drop(read_guard);
+
+    let limit = state.receipts.max_bytes;
+    let mut upload = None;
+    while let Some(field) = form.next().await {
+        let mut field = match field {
+            Ok(field) => field,
+            Err(e) => {
+                return HttpResponse::BadRequest().json(serde_json::json!({
+                    "error": "invalid multipart body",
+                    "detail": e.to_string()
+                }));
+            }
+        };
+        if field.name() != Some("file") {
+            continue;
+        }
+        let content_type = field
+            .content_type()
+            .map(|m| m.essence_str().to_string())
+            .unwrap_or_default();
+        if !Receipts::accepts(&content_type) {
+            return HttpResponse::UnsupportedMediaType().json(serde_json::json!({
+                "error": "receipts must be JPEG, PNG, GIF, WebP or PDF",
+                "content_type": content_type
+            }));
+        }
+        let filename = field
+            .content_disposition()
+            .and_then(|cd| cd.get_filename())
+            .unwrap_or_default()
+            .to_string();
+        let mut data = Vec::new();
+        while let Some(chunk) = field.next().await {
+            match chunk {
+                Ok(chunk) if data.len() + chunk.len() > limit => return payload_too_large(limit),
+                Ok(chunk) => data.extend_from_slice(&chunk),
+                Err(e) => {
+                    return HttpResponse::BadRequest().json(serde_json::json!({
+                        "error": "invalid multipart body",
+                        "detail": e.to_string()
+                    }));
+                }
+            }
+        }
+        upload = Some((filename, content_type, data));
+        break;
+    }
+    let Some((filename, content_type, data)) = upload else {
+        return HttpResponse::BadRequest()
+            .json(serde_json::json!({"error":"expected a `file` field"}));
+    };
+
+    let stored = match state
+        .receipts
+        .save(id, &filename, &content_type, &data)
+        .await
+    {
+        Ok(stored) => stored,
+        Err(e) => {
+            tracing::error!(error = %e, %id, "Failed to store receipt");
+            return HttpResponse::InternalServerError()
+                .json(serde_json::json!({"error":"failed to store receipt"}));
+        }
+    };
+
+    let updated = {
+        let Some(mut write_guard) = state.write_ledger().await else {
+            return lock_timeout();
+        };
+        let found = write_guard.update(&id, |tx| {
+            let before = tx.clone();
+            tx.receipt = Some(stored);
+            tx.bump_version();
+            state
+                .undo
+                .record(Step::new("receipt", Inverse::Revert(vec![before])));
+            tx.clone()
+        });
+        match found {
+            Some(updated) => updated,
+            None => return HttpResponse::NotFound().json(serde_json::json!({"error":"not found"})),
+        }
+    };
+
+    if let Err(e) = state.persist().await {
+        tracing::error!(error = %e, "Failed to persist receipt");
+        return persist_failed("failed to save transaction");
+    }
+    state.metrics.updated.inc();
+    state.notify(ChangeKind::Updated, &updated).await;
+
+    HttpResponse::Ok().json(updated)
+}
+
+#[utoipa::path(
+    tag = "transactions",
+    params(("id" = Uuid, Path, description = "transaction id")),
+    responses(
+        (status = 200, description = "the stored file, served with its image or PDF content type"),
+        (status = 404, description = "not found or no receipt uploaded"),
+    )
+)]
+#[get("/transactions/{id}/receipt")]
+async fn get_receipt(state: web::Data<AppState>, path: web::Path<String>) -> impl Responder {
+    let id = match Uuid::parse_str(&path) {
+        Ok(u) => u,
+        Err(_) => {
+            return HttpResponse::BadRequest().json(serde_json::json!({"error":"invalid uuid"}));
+        }
+    };
+    let Some(read_guard) = state.read_ledger().await else {
+        return lock_timeout();
+    };
+    let stored = match read_guard.get(&id) {
+        Some(tx) => tx.receipt.clone(),
+        None => return HttpResponse::NotFound().json(serde_json::json!({"error":"not found"})),
+    };
+    drop(read_guard);
+    let Some(stored) = stored else {
+        return HttpResponse::NotFound().json(serde_json::json!({"error":"no receipt uploaded"}));
+    };
+    match state.receipts.read(&stored).await {
+        Ok((data, content_type)) => {
+            let filename = std::path::Path::new(&stored)
+                .file_name()
+                .map(|n| n.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            HttpResponse::Ok()
+                .content_type(content_type)
+                .insert_header((
+                    "Content-Disposition",
+                    format!("inline; filename=\"{}\"", filename),
+                ))
+                .body(data)
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            HttpResponse::NotFound().json(serde_json::json!({"error":"receipt file is missing"}))
+        }
+        Err(e) => {
+            tracing::error!(error = %e, %id, "Failed to read receipt");
+            HttpResponse::InternalServerError()
+                .json(serde_json::json!({"error":"failed to read receipt"}))
+        }
+    }
+}
+
+/// Move money between two users: a debit for the sender and a credit for the
+/// receiver, linked by a shared `transfer_id`.
+#[utoipa::path(
+    tag = "transactions",
+    request_body = CreateTransfer,
+    responses(
+        (status = 201, body = Transfer),
+        (status = 400, description = "validation failed"),
+        (status = 409, description = "If-Match no longer matches the collection"),
+    )
+)]
+#[post("/transfers")]
+async fn create_transfer(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    payload: web::Json<CreateTransfer>,
+) -> impl Responder {
+    if let Err(errors) = payload.validate() {
+        return validation_failed(errors);
+    }
+    let mut transfer = payload.into_inner().into_transfer();
+
+    {
+        // both legs appear together or not at all
+        let Some(mut write_guard) = state.write_ledger().await else {
+            return lock_timeout();
+        };
+        if let Some(stale) = stale_collection(&req, &write_guard) {
+            return stale;
+        }
+        transfer.debit.seq = write_guard.next_seq();
+        transfer.credit.seq = write_guard.next_seq();
+        write_guard.push(transfer.debit.clone());
+        write_guard.push(transfer.credit.clone());
+        state.undo.record(Step::new(
+            "transfer",
+            Inverse::Remove(vec![transfer.debit.id, transfer.credit.id]),
+        ));
+    }
+
+    if let Err(e) = state.persist().await {
+        tracing::error!(error = %e, "Failed to persist transfer");
+        return persist_failed("failed to save transfer");
+    }
+    state.metrics.created.inc_by(2);
+    state.notify(ChangeKind::Created, &transfer.debit).await;
+    state.notify(ChangeKind::Created, &transfer.credit).await;
+
+    HttpResponse::Created().json(transfer)
+}
+
+/// Replace one transaction with several smaller ones, e.g. a receipt that
+/// covers more than one category. The original is soft-deleted.
+#[utoipa::path(
+    tag = "transactions",
+    params(("id" = Uuid, Path, description = "transaction to split"), ("If-Match" = Option<String>, Header, description = "expected version of the original")),
+    request_body = Vec<SplitPart>,
+    responses(
+        (status = 201, description = "the new transactions", body = Vec<Transaction>),
+        (status = 400, description = "invalid parts, or amounts that don't add up to the original"),
+        (status = 404, description = "not found"),
+        (status = 409, description = "original is deleted or a transfer leg, or version mismatch"),
+        (status = 423, description = "original is locked by a period close"),
+    )
+)]
+#[post("/transactions/{id}/split")]
+async fn split_transaction(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    path: web::Path<String>,
+    payload: web::Json<Vec<SplitPart>>,
+) -> impl Responder {
+    let id = match Uuid::parse_str(&path) {
+        Ok(u) => u,
+        Err(_) => {
+            return HttpResponse::BadRequest().json(serde_json::json!({"error":"invalid uuid"}));
+        }
+    };
+    let expected = match expected_version(&req, None) {
+        Ok(v) => v,
+        Err(response) => return *response,
+    };
+    if payload.len() < 2 {
+        return HttpResponse::BadRequest()
+            .json(serde_json::json!({"error":"a split needs at least two parts"}));
+    }
+    let failed: Vec<serde_json::Value> = payload
+        .iter()
+        .enumerate()
+        .filter_map(|(index, p)| {
+            p.validate()
+                .err()
+                .map(|errors| serde_json::json!({"index": index, "errors": errors}))
+        })
+        .collect();
+    if !failed.is_empty() {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "split validation failed",
+            "failed": failed
+        }));
+    }
+
+    let (original, parts) = {
+        let Some(mut write_guard) = state.write_ledger().await else {
+            return lock_timeout();
+        };
+        let Some(source) = write_guard.get(&id) else {
+            return HttpResponse::NotFound().json(serde_json::json!({"error":"not found"}));
+        };
+        if source.locked {
+            return locked_response(source.id);
+        }
+        if source.deleted {
+            return HttpResponse::Conflict().json(serde_json::json!({
+                "error": "transaction is deleted; restore it before splitting"
+            }));
+        }
+        if let Some(stale) = stale_version(expected, source) {
+            return stale;
+        }
+        // the parts would all claim the pair, leaving its two sides unequal
+        if source.transfer_id.is_some() {
+            return HttpResponse::Conflict().json(serde_json::json!({
+                "error": "transaction is one leg of a transfer and cannot be split"
+            }));
+        }
+        let kind = EntryKind::of(source.amount);
+        let mut parts: Vec<Transaction> = payload
+            .iter()
+            .map(|p| Transaction {
+                id: Uuid::new_v4(),
+                item: p.item.trim().to_string(),
+                amount: round_amount(kind.signed(p.amount), &source.currency),
+                category: p.category.as_ref().map(|c| c.trim().to_string()),
+                deleted: false,
+                version: initial_version(),
+                transfer_id: None,
+                // the file stays with the original
+                receipt: None,
+                locked: false,
+                ..source.clone()
+            })
+            .collect();
+
+        // parts are rounded on their own, so allow them to be off by one minor unit
+        let sum: Decimal = parts.iter().map(|t| t.amount).sum();
+        let tolerance = Decimal::new(1, currency_decimals(&source.currency));
+        if (sum - source.amount).abs() > tolerance {
+            return HttpResponse::BadRequest().json(serde_json::json!({
+                "error": "parts must add up to the original amount",
+                "expected": source.amount.abs(),
+                "actual": sum.abs()
+            }));
+        }
+
+        let before = source.clone();
+        let Some(original) = write_guard.update(&id, |tx| {
+            tx.deleted = true;
+            tx.bump_version();
+            tx.clone()
+        }) else {
+            return HttpResponse::NotFound().json(serde_json::json!({"error":"not found"}));
+        };
+        write_guard.number(&mut parts);
+        write_guard.extend(parts.iter().cloned());
+        let ids = parts.iter().map(|t| t.id).collect();
+        state.undo.record(Step::new(
+            "split",
+            Inverse::All(vec![Inverse::Remove(ids), Inverse::Revert(vec![before])]),
+        ));
+        (original, parts)
+    };
+
+    if let Err(e) = state.persist().await {
+        tracing::error!(error = %e, "Failed to persist split");
+        return persist_failed("failed to save transactions");
+    }
+    state.metrics.deleted.inc();
+    state.metrics.created.inc_by(parts.len() as u64);
+    state.notify(ChangeKind::Deleted, &original).await;
+    for tx in &parts {
+        state.notify(ChangeKind::Created, tx).await;
+    }
+
+    HttpResponse::Created().json(parts)
+}
+
+#[utoipa::path(
+    tag = "transactions",
+    request_body = Vec<String>,
+    responses(
+        (status = 200, description = "`{ deleted, not_found, locked, invalid }`"),
+        (status = 409, description = "If-Match no longer matches the collection"),
+    )
+)]
+#[post("/transactions/bulk-delete")]
+async fn bulk_delete_transactions(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    payload: web::Json<Vec<String>>,
+) -> impl Responder {
+    let mut invalid = Vec::new();
+    let mut locked = Vec::new();
+    let mut requested = HashSet::new();
+    for id_str in payload.iter() {
+        match Uuid::parse_str(id_str) {
+            Ok(id) => {
+                requested.insert(id);
+            }
+            Err(_) => invalid.push(id_str.clone()),
+        }
+    }
+
+    let deleted = {
+        let Some(mut write_guard) = state.write_ledger().await else {
+            return lock_timeout();
+        };
+        if let Some(stale) = stale_collection(&req, &write_guard) {
+            return stale;
+        }
+        let mut deleted = Vec::new();
+        let mut before = Vec::new();
+        requested.retain(|id| {
+            let removed = write_guard.update(id, |tx| {
+                if tx.deleted {
+                    return None;
+                }
+                if tx.locked {
+                    locked.push(tx.id);
+                    return None;
+                }
+                before.push(tx.clone());
+                tx.deleted = true;
+                tx.bump_version();
+                Some(tx.clone())
+            });
+            match removed {
+                Some(Some(tx)) => {
+                    deleted.push(tx);
+                    false
+                }
+                _ => !locked.contains(id),
+            }
+        });
+        if !before.is_empty() {
+            state
+                .undo
+                .record(Step::new("bulk-delete", Inverse::Revert(before)));
+        }
+        deleted
+    };
+    // whatever was not removed from the set had no live matching transaction
+    let not_found: Vec<Uuid> = requested.into_iter().collect();
+
+    if !deleted.is_empty()
+        && let Err(e) = state.persist().await
+    {
+        tracing::error!(error = %e, "Failed to persist after bulk delete");
+        return persist_failed("failed to persist delete");
+    }
+    state.metrics.deleted.inc_by(deleted.len() as u64);
+    for tx in &deleted {
+        state.notify(ChangeKind::Deleted, tx).await;
+    }
+
+    HttpResponse::Ok().json(serde_json::json!({
+        "deleted": deleted.len(),
+        "not_found": not_found,
+        "locked": locked,
+        "invalid": invalid
+    }))
+}
+
+/// Body for POST /transactions/bulk-update.
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct BulkUpdate {
+    pub ids: Vec<String>,
+    /// applied to every id as a PATCH would be; `version` isn't allowed since
+    /// each row has its own
+    pub patch: UpdateTransaction,
+}
+
+/// Apply one patch to many transactions, e.g. to recategorize them. The patch
+/// is validated against every row before any is changed, and the result is
+/// saved once.
+#[utoipa::path(
+    tag = "transactions",
+    request_body = BulkUpdate,
+    responses(
+        (status = 200, description = "`{ updated, not_found, locked, invalid }`"),
+        (status = 400, description = "no ids, or the patch failed validation; nothing was changed"),
+        (status = 409, description = "If-Match no longer matches the collection"),
+    )
+)]
+#[post("/transactions/bulk-update")]
+async fn bulk_update_transactions(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    payload: web::Json<BulkUpdate>,
+) -> impl Responder {
+    if payload.ids.is_empty() {
+        return validation_failed(vec![FieldError::new("ids", "must list at least one id")]);
+    }
+    if payload.patch.version.is_some() {
+        return validation_failed(vec![FieldError::new(
+            "patch.version",
+            "not supported in a bulk update",
+        )]);
+    }
+    let mut invalid = Vec::new();
+    let mut requested = Vec::new();
+    for id_str in &payload.ids {
+        match Uuid::parse_str(id_str) {
+            Ok(id) if !requested.contains(&id) => requested.push(id),
+            Ok(_) => {}
+            Err(_) => invalid.push(id_str.clone()),
+        }
+    }
+
+    let mut not_found = Vec::new();
+    let mut locked = Vec::new();
+    let updated = {
+        let Some(mut write_guard) = state.write_ledger().await else {
+            return lock_timeout();
+        };
+        if let Some(stale) = stale_collection(&req, &write_guard) {
+            return stale;
+        }
+        // patch copies first so one failing row leaves every row untouched
+        let mut patched = Vec::new();
+        for id in &requested {
+            match write_guard.get(id) {
+                Some(tx) if tx.deleted => not_found.push(*id),
+                Some(tx) if tx.locked => locked.push(*id),
+                Some(tx) => {
+                    let mut copy = tx.clone();
+                    if let Err(errors) = apply_patch(&mut copy, &payload.patch) {
+                        return validation_failed(errors);
+                    }
+                    copy.bump_version();
+                    patched.push(copy);
+                }
+                None => not_found.push(*id),
+            }
+        }
+        let mut before = Vec::with_capacity(patched.len());
+        for copy in &patched {
+            write_guard.update(&copy.id, |tx| {
+                before.push(tx.clone());
+                *tx = copy.clone();
+            });
+        }
+        if !before.is_empty() {
+            state
+                .undo
+                .record(Step::new("bulk-update", Inverse::Revert(before)));
+        }
+        patched
+    };
+
+    if !updated.is_empty()
+        && let Err(e) = state.persist().await
+    {
+        tracing::error!(error = %e, "Failed to persist after bulk update");
+        return persist_failed("failed to save changes");
+    }
+    state.metrics.updated.inc_by(updated.len() as u64);
+    for tx in &updated {
+        state.notify(ChangeKind::Updated, tx).await;
+    }
+
+    HttpResponse::Ok().json(serde_json::json!({
+        "updated": updated.len(),
+        "not_found": not_found,
+        "locked": locked,
+        "invalid": invalid
+    }))
+}
+
+/// Median of a non-empty slice; sorts it in place.
+fn median(values: &mut [Decimal]) -> Decimal {
+    values.sort();
+    let mid = values.len() / 2;
+    if values.len().is_multiple_of(2) {
+        (values[mid - 1] + values[mid]) / Decimal::TWO
+    } else {
+        values[mid]
+    }
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct DistinctQuery {
+    /// return `{ <field>, count }` objects instead of bare values
+    #[serde(default)]
+    pub with_counts: bool,
+}
+
+/// Sorted distinct values of one field, as bare strings or, with counts, as
+/// objects named after the field. With `fold` set, values that differ only in
+/// case or spacing are one entry, shown as the first spelling seen.
+fn distinct_values<'a>(
+    txs: impl Iterator<Item = &'a Transaction>,
+    field: &str,
+    value: impl Fn(&'a Transaction) -> Option<&'a str>,
+    fold: bool,
+    with_counts: bool,
+) -> HttpResponse {
+    let mut counts: BTreeMap<Cow<str>, (&str, usize)> = BTreeMap::new();
+    for v in txs.filter_map(value) {
+        let key = if fold {
+            Cow::Owned(fold_item(v))
+        } else {
+            Cow::Borrowed(v)
+        };
+        counts.entry(key).or_insert((v, 0)).1 += 1;
+    }
+    if with_counts {
+        let values: Vec<_> = counts
+            .into_values()
+            .map(|(v, count)| serde_json::json!({field: v, "count": count}))
+            .collect();
+        HttpResponse::Ok().json(values)
+    } else {
+        HttpResponse::Ok().json(counts.into_values().map(|(v, _)| v).collect::<Vec<_>>())
+    }
+}
+
+/// Every user with at least one transaction, sorted by name.
+#[utoipa::path(
+    tag = "users",
+    params(DistinctQuery, DeletedFilter),
+    responses((status = 200, description = "user names, or `{ user, count }` objects with ?with_counts=true"))
+)]
+#[get("/users")]
+async fn list_users(
+    state: web::Data<AppState>,
+    query: web::Query<DistinctQuery>,
+    deleted: web::Query<DeletedFilter>,
+) -> impl Responder {
+    let Some(read_guard) = state.read_ledger().await else {
+        return lock_timeout();
+    };
+    distinct_values(
+        read_guard.iter().filter(|t| deleted.allows(t)),
+        "user",
+        |t| Some(&t.user),
+        false,
+        query.with_counts,
+    )
+}
+
+/// Distinct item names, for autocomplete.
+#[utoipa::path(
+    tag = "transactions",
+    params(DistinctQuery, DeletedFilter),
+    responses((status = 200, description = "item names, or `{ item, count }` objects with ?with_counts=true"))
+)]
+#[get("/items")]
+async fn list_items(
+    state: web::Data<AppState>,
+    query: web::Query<DistinctQuery>,
+    deleted: web::Query<DeletedFilter>,
+) -> impl Responder {
+    let Some(read_guard) = state.read_ledger().await else {
+        return lock_timeout();
+    };
+    distinct_values(
+        read_guard.iter().filter(|t| deleted.allows(t)),
+        "item",
+        |t| Some(&t.item),
+        state.config.fold_item_names,
+        query.with_counts,
+    )
+}
+
+/// Distinct categories in use; uncategorized transactions are left out.
+#[utoipa::path(
+    tag = "transactions",
+    params(DistinctQuery, DeletedFilter),
+    responses((status = 200, description = "categories, or `{ category, count }` objects with ?with_counts=true"))
+)]
+#[get("/categories")]
+async fn list_categories(
+    state: web::Data<AppState>,
+    query: web::Query<DistinctQuery>,
+    deleted: web::Query<DeletedFilter>,
+) -> impl Responder {
+    let Some(read_guard) = state.read_ledger().await else {
+        return lock_timeout();
+    };
+    distinct_values(
+        read_guard.iter().filter(|t| deleted.allows(t)),
+        "category",
+        |t| t.category.as_deref(),
+        false,
+        query.with_counts,
+    )
+}
+
+/// Move every transaction of one user, soft-deleted ones included, to another
+/// user in a single step.
+#[utoipa::path(
+    tag = "users",
+    params(("user" = String, Path, description = "user to move from"), ("target" = String, Path, description = "user to move to")),
+    responses(
+        (status = 200, description = "`{ moved, from, to }`"),
+        (status = 400, description = "target is empty or the same as the source"),
+        (status = 409, description = "If-Match no longer matches the collection"),
+        (status = 423, description = "some of the user's entries are locked by a period close"),
+    )
+)]
+#[post("/users/{user}/merge-into/{target}")]
+async fn merge_user(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    path: web::Path<(String, String)>,
+) -> impl Responder {
+    let (source, target) = path.into_inner();
+    let target = target.trim().to_string();
+    if target.is_empty() {
+        return validation_failed(vec![FieldError::new("target", "required")]);
+    }
+    if target == source {
+        return validation_failed(vec![FieldError::new(
+            "target",
+            "must differ from the user being merged",
+        )]);
+    }
+
+    let moved = {
+        let Some(mut write_guard) = state.write_ledger().await else {
+            return lock_timeout();
+        };
+        if let Some(stale) = stale_collection(&req, &write_guard) {
+            return stale;
+        }
+        if let Some(locked) = write_guard.for_user(&source).find(|t| t.locked) {
+            return locked_response(locked.id);
+        }
+        let ids: Vec<Uuid> = write_guard.for_user(&source).map(|t| t.id).collect();
+        let mut before = Vec::with_capacity(ids.len());
+        let mut moved = Vec::with_capacity(ids.len());
+        for id in &ids {
+            write_guard.update(id, |tx| {
+                before.push(tx.clone());
+                tx.user = target.clone();
+                tx.bump_version();
+                moved.push(tx.clone());
+            });
+        }
+        if !before.is_empty() {
+            state
+                .undo
+                .record(Step::new("merge", Inverse::Revert(before)));
+        }
+        moved
+    };
+
+    if !moved.is_empty()
+        && let Err(e) = state.persist().await
+    {
+        tracing::error!(error = %e, "Failed to persist user merge");
+        return persist_failed("failed to save changes");
+    }
+    state.metrics.updated.inc_by(moved.len() as u64);
+    for tx in &moved {
+        state.notify(ChangeKind::Updated, tx).await;
+    }
+
+    HttpResponse::Ok().json(serde_json::json!({
+        "moved": moved.len(),
+        "from": source,
+        "to": target
+    }))
+}
+
+#[utoipa::path(
+    tag = "reports",
+    params(("user" = String, Path), DeletedFilter),
+    responses((status = 200, description = "per-currency totals, averages, medians, category breakdown and this month's budget status"))
+)]
+#[get("/users/{user}/summary")]
+async fn user_summary(
+    state: web::Data<AppState>,
+    path: web::Path<String>,
+    deleted: web::Query<DeletedFilter>,
+) -> impl Responder {
     let user = path.into_inner();
-    let read_guard = state.transactions.read().await;
-    let user_txs: Vec<Transaction> = read_guard
-        .iter()
-        .filter(|t| t.user == user)
-        .cloned()
+    let Some(read_guard) = state.read_ledger().await else {
+        return lock_timeout();
+    };
+    let user_txs: Vec<&Transaction> = read_guard
+        .for_user(&user)
+        .filter(|t| deleted.allows(t))
         .collect();
-    let total: f64 = user_txs.iter().map(|t| t.amount).sum();
     let count = user_txs.len();
+    // amounts in different currencies are never added together
+    let mut totals: BTreeMap<&str, Decimal> = BTreeMap::new();
+    let mut by_category: BTreeMap<&str, BTreeMap<&str, Decimal>> = BTreeMap::new();
+    let mut amounts: BTreeMap<&str, Vec<Decimal>> = BTreeMap::new();
+    for t in &user_txs {
+        *totals.entry(&t.currency).or_default() += t.amount;
+        amounts.entry(&t.currency).or_default().push(t.amount);
+        let key = t.category.as_deref().unwrap_or("uncategorized");
+        *by_category
+            .entry(key)
+            .or_default()
+            .entry(&t.currency)
+            .or_default() += t.amount;
+    }
+    // a currency only appears once it has an amount, so neither divides by zero
+    let mut average: BTreeMap<&str, Decimal> = totals
+        .iter()
+        .map(|(&currency, &total)| (currency, total / Decimal::from(amounts[currency].len())))
+        .collect();
+    let mut median: BTreeMap<&str, Decimal> = amounts
+        .iter_mut()
+        .map(|(&currency, values)| (currency, median(values)))
+        .collect();
+    for map in [&mut totals, &mut average, &mut median]
+        .into_iter()
+        .chain(by_category.values_mut())
+    {
+        round_totals(map);
+    }
+    // budgets always look at live entries in the current UTC month, whatever the filter
+    let budgets = state.budgets.budgets.read().await;
+    let live: Vec<&Transaction> = read_guard.for_user(&user).filter(|t| !t.deleted).collect();
+    let budget_status: Vec<_> = match StatementPeriod::containing(now_secs()) {
+        Some(month) => budgets
+            .iter()
+            .filter(|b| b.user == user)
+            .map(|b| b.status(&month, &live))
+            .collect(),
+        None => Vec::new(),
+    };
+    HttpResponse::Ok().json(serde_json::json!({
+        "user": user,
+        "count": count,
+        "total_amount": totals,
+        "average_amount": average,
+        "median_amount": median,
+        "by_category": by_category,
+        "budgets": budget_status,
+        "transactions": user_txs
+    }))
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, Default, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum StatementFormat {
+    #[default]
+    Html,
+    Json,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct StatementQuery {
+    /// calendar month in UTC, `YYYY-MM`
+    pub month: Option<String>,
+    /// defaults to html
+    pub format: Option<StatementFormat>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ConvertQuery {
+    /// ISO 4217 code to express the grand total in, using the rate table
+    pub convert_to: Option<String>,
+}
+
+#[derive(Debug, Default, Serialize)]
+struct UserTotals<'a> {
+    count: usize,
+    /// keyed by currency, like every other money total
+    total: BTreeMap<&'a str, Decimal>,
+}
+
+/// A printable per-month statement for one user, as an HTML page or JSON.
+#[utoipa::path(
+    tag = "reports",
+    params(("user" = String, Path), StatementQuery),
+    responses(
+        (status = 200, description = "HTML page (default) or JSON with the month's transactions and totals"),
+        (status = 400, description = "missing or malformed month"),
+    )
+)]
+#[get("/users/{user}/statement")]
+async fn user_statement(
+    state: web::Data<AppState>,
+    path: web::Path<String>,
+    query: web::Query<StatementQuery>,
+) -> impl Responder {
+    let Some(period) = query.month.as_deref().and_then(StatementPeriod::parse) else {
+        return HttpResponse::BadRequest()
+            .json(serde_json::json!({"error":"month must be given as YYYY-MM"}));
+    };
+    let user = path.into_inner();
+    let Some(read_guard) = state.read_ledger().await else {
+        return lock_timeout();
+    };
+    let txs: Vec<&Transaction> = read_guard
+        .for_user(&user)
+        .filter(|t| !t.deleted && period.contains(t.timestamp))
+        .collect();
+    let statement = Statement::new(&user, &period, txs);
+    match query.format.unwrap_or_default() {
+        StatementFormat::Html => HttpResponse::Ok()
+            .content_type("text/html; charset=utf-8")
+            .body(statement.to_html(state.config.locale)),
+        StatementFormat::Json => HttpResponse::Ok().json(statement),
+    }
+}
+
+#[utoipa::path(
+    tag = "reports",
+    params(DeletedFilter, ConvertQuery),
+    responses(
+        (status = 200, description = "grand totals and per-user totals by currency, plus a converted total when asked"),
+        (status = 400, description = "convert_to is not a currency code"),
+    )
+)]
+#[get("/report/summary")]
+async fn report_summary(
+    state: web::Data<AppState>,
+    deleted: web::Query<DeletedFilter>,
+    convert: web::Query<ConvertQuery>,
+) -> impl Responder {
+    let target = match convert.convert_to.as_deref().map(str::trim) {
+        Some(code) if !is_valid_currency(code) => {
+            return HttpResponse::BadRequest().json(serde_json::json!({ "error": CURRENCY_ERROR }));
+        }
+        other => other,
+    };
+    let Some(read_guard) = state.read_ledger().await else {
+        return lock_timeout();
+    };
+    let mut grand_total: BTreeMap<&str, Decimal> = BTreeMap::new();
+    let mut users: BTreeMap<&str, UserTotals> = BTreeMap::new();
+    let mut transaction_count = 0;
+    for t in read_guard.iter().filter(|t| deleted.allows(t)) {
+        transaction_count += 1;
+        *grand_total.entry(&t.currency).or_default() += t.amount;
+        let entry = users.entry(&t.user).or_default();
+        entry.count += 1;
+        *entry.total.entry(&t.currency).or_default() += t.amount;
+    }
+    round_totals(&mut grand_total);
+    for entry in users.values_mut() {
+        round_totals(&mut entry.total);
+    }
+    let mut body = serde_json::json!({
+        "grand_total": grand_total,
+        "transaction_count": transaction_count,
+        "users": users
+    });
+    if let Some(target) = target {
+        let mut total = Decimal::ZERO;
+        // totals we have no rate for stay in their own currency rather than failing the report
+        let mut unconverted: BTreeMap<&str, Decimal> = BTreeMap::new();
+        for (&currency, &amount) in &grand_total {
+            match state.rates.convert(amount, currency, target) {
+                Some(converted) => total += converted,
+                None => *unconverted.entry(currency).or_default() += amount,
+            }
+        }
+        body["converted"] = serde_json::json!({
+            "currency": target,
+            "total": round_amount(total, target),
+            "unconverted": unconverted
+        });
+    }
+    HttpResponse::Ok().json(body)
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, Default, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Bucket {
+    Day,
+    Week,
+    #[default]
+    Month,
+}
+
+impl Bucket {
+    /// Period label for a timestamp; labels sort chronologically as strings.
+    fn period(self, timestamp: u64) -> Option<String> {
+        let dt: DateTime<Utc> = DateTime::from_timestamp(i64::try_from(timestamp).ok()?, 0)?;
+        Some(match self {
+            Bucket::Day => dt.format("%Y-%m-%d").to_string(),
+            Bucket::Week => {
+                let week = dt.iso_week();
+                format!("{}-W{:02}", week.year(), week.week())
+            }
+            Bucket::Month => dt.format("%Y-%m").to_string(),
+        })
+    }
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct TimeseriesQuery {
+    /// defaults to month
+    pub bucket: Option<Bucket>,
+    pub user: Option<String>,
+}
+
+#[derive(Debug, Default, Serialize)]
+struct PeriodTotals<'a> {
+    total: BTreeMap<&'a str, Decimal>,
+    count: usize,
+}
+
+#[utoipa::path(
+    tag = "reports",
+    params(TimeseriesQuery, DeletedFilter),
+    responses((status = 200, description = "totals per period and currency"))
+)]
+#[get("/report/timeseries")]
+async fn report_timeseries(
+    state: web::Data<AppState>,
+    query: web::Query<TimeseriesQuery>,
+    deleted: web::Query<DeletedFilter>,
+) -> impl Responder {
+    let bucket = query.bucket.unwrap_or_default();
+    let Some(read_guard) = state.read_ledger().await else {
+        return lock_timeout();
+    };
+    let mut periods: BTreeMap<String, PeriodTotals> = BTreeMap::new();
+    let candidates: Box<dyn Iterator<Item = &Transaction>> = match &query.user {
+        Some(user) => Box::new(read_guard.for_user(user)),
+        None => Box::new(read_guard.iter()),
+    };
+    for t in candidates.filter(|t| deleted.allows(t)) {
+        // timestamps chrono can't represent are left out rather than misfiled
+        let Some(period) = bucket.period(t.timestamp) else {
+            continue;
+        };
+        let entry = periods.entry(period).or_default();
+        entry.count += 1;
+        *entry.total.entry(&t.currency).or_default() += t.amount;
+    }
+
+    let series: Vec<serde_json::Value> = periods
+        .into_iter()
+        .map(|(period, mut totals)| {
+            round_totals(&mut totals.total);
+            serde_json::json!({
+                "period": period,
+                "total": totals.total,
+                "count": totals.count
+            })
+        })
+        .collect();
+    HttpResponse::Ok().json(series)
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct TopQuery {
+    /// defaults to DEFAULT_TOP_N
+    pub n: Option<usize>,
+}
+
+#[derive(Debug, Serialize)]
+struct RankedTotal<'a> {
+    name: &'a str,
+    currency: &'a str,
+    total: Decimal,
+    count: usize,
+}
+
+/// Sum amounts per (key, currency) and return the n largest totals, descending.
+/// With `fold` set, keys that differ only in case or spacing are summed
+/// together under the first spelling seen.
+fn top_n<'a>(
+    txs: impl IntoIterator<Item = &'a Transaction>,
+    key: impl Fn(&'a Transaction) -> &'a str,
+    fold: bool,
+    n: usize,
+) -> Vec<RankedTotal<'a>> {
+    let mut totals: HashMap<(Cow<str>, &str), (&str, Decimal, usize)> = HashMap::new();
+    for t in txs {
+        let name = key(t);
+        let group = if fold {
+            Cow::Owned(fold_item(name))
+        } else {
+            Cow::Borrowed(name)
+        };
+        let entry = totals
+            .entry((group, &t.currency))
+            .or_insert((name, Decimal::ZERO, 0));
+        entry.1 += t.amount;
+        entry.2 += 1;
+    }
+    let mut ranked: Vec<RankedTotal> = totals
+        .into_iter()
+        .map(|((_, currency), (name, total, count))| RankedTotal {
+            name,
+            currency,
+            total: round_amount(total, currency),
+            count,
+        })
+        .collect();
+    // tie-break on name so equal totals come back in a stable order
+    ranked.sort_by(|a, b| b.total.cmp(&a.total).then(a.name.cmp(b.name)));
+    ranked.truncate(n);
+    ranked
+}
+
+#[utoipa::path(
+    tag = "reports",
+    params(TopQuery, DeletedFilter),
+    responses((status = 200, description = "items ranked by total spend"))
+)]
+#[get("/report/top-items")]
+async fn report_top_items(
+    state: web::Data<AppState>,
+    query: web::Query<TopQuery>,
+    deleted: web::Query<DeletedFilter>,
+) -> impl Responder {
+    let Some(read_guard) = state.read_ledger().await else {
+        return lock_timeout();
+    };
+    let ranked = top_n(
+        read_guard.iter().filter(|t| deleted.allows(t)),
+        |t| &t.item,
+        state.config.fold_item_names,
+        query.n.unwrap_or(DEFAULT_TOP_N),
+    );
+    HttpResponse::Ok().json(ranked)
+}
+
+#[utoipa::path(
+    tag = "reports",
+    params(TopQuery, DeletedFilter),
+    responses((status = 200, description = "users ranked by total spend"))
+)]
+#[get("/report/top-users")]
+async fn report_top_users(
+    state: web::Data<AppState>,
+    query: web::Query<TopQuery>,
+    deleted: web::Query<DeletedFilter>,
+) -> impl Responder {
+    let Some(read_guard) = state.read_ledger().await else {
+        return lock_timeout();
+    };
+    let ranked = top_n(
+        read_guard.iter().filter(|t| deleted.allows(t)),
+        |t| &t.user,
+        false,
+        query.n.unwrap_or(DEFAULT_TOP_N),
+    );
+    HttpResponse::Ok().json(ranked)
+}
+
+#[utoipa::path(
+    tag = "recurring",
+    request_body = CreateRecurring,
+    responses((status = 201, body = RecurringTransaction), (status = 400, description = "validation failed"))
+)]
+#[post("/recurring")]
+async fn create_recurring(
+    state: web::Data<AppState>,
+    payload: web::Json<CreateRecurring>,
+) -> impl Responder {
+    if let Err(errors) = payload.validate() {
+        return validation_failed(errors);
+    }
+    let recurring = payload.into_inner().into_recurring();
+
+    let mut templates = state.recurring.templates.write().await;
+    templates.push(recurring.clone());
+    if let Err(e) = state.recurring.save(&templates).await {
+        templates.pop();
+        tracing::error!(error = %e, "Failed to persist recurring template");
+        return HttpResponse::InternalServerError()
+            .json(serde_json::json!({"error":"failed to save recurring transaction"}));
+    }
+
+    HttpResponse::Created()
+        .insert_header((
+            header::LOCATION,
+            state.url(&format!("/recurring/{}", recurring.id)),
+        ))
+        .json(recurring)
+}
+
+#[utoipa::path(
+    tag = "recurring",
+    params(UserFilter),
+    responses((status = 200, description = "`{ total, items }`"))
+)]
+#[get("/recurring")]
+async fn list_recurring(
+    state: web::Data<AppState>,
+    user: web::Query<UserFilter>,
+) -> impl Responder {
+    let templates = state.recurring.templates.read().await;
+    let items: Vec<_> = templates
+        .iter()
+        .filter(|r| {
+            user.user
+                .as_deref()
+                .is_none_or(|u| r.template.user.trim() == u.trim())
+        })
+        .collect();
+    HttpResponse::Ok().json(serde_json::json!({"total": items.len(), "items": items}))
+}
+
+#[utoipa::path(
+    tag = "recurring",
+    params(("id" = Uuid, Path, description = "recurring template id")),
+    responses((status = 200, body = RecurringTransaction), (status = 404, description = "not found"))
+)]
+#[get("/recurring/{id}")]
+async fn get_recurring(state: web::Data<AppState>, path: web::Path<String>) -> impl Responder {
+    let Ok(id) = Uuid::parse_str(&path) else {
+        return HttpResponse::BadRequest().json(serde_json::json!({"error":"invalid uuid"}));
+    };
+    let templates = state.recurring.templates.read().await;
+    match templates.iter().find(|r| r.id == id) {
+        Some(r) => HttpResponse::Ok().json(r),
+        None => HttpResponse::NotFound().json(serde_json::json!({"error":"not found"})),
+    }
+}
+
+/// Replace a template. The schedule carries on from where it was unless the
+/// body sets `timestamp`, which restarts it from that point.
+#[utoipa::path(
+    tag = "recurring",
+    params(("id" = Uuid, Path, description = "recurring template id")),
+    request_body = CreateRecurring,
+    responses((status = 200, body = RecurringTransaction), (status = 400, description = "validation failed"), (status = 404, description = "not found"))
+)]
+#[put("/recurring/{id}")]
+async fn update_recurring(
+    state: web::Data<AppState>,
+    path: web::Path<String>,
+    payload: web::Json<CreateRecurring>,
+) -> impl Responder {
+    let Ok(id) = Uuid::parse_str(&path) else {
+        return HttpResponse::BadRequest().json(serde_json::json!({"error":"invalid uuid"}));
+    };
+    if let Err(errors) = payload.validate() {
+        return validation_failed(errors);
+    }
+    let restart = payload.template.timestamp.is_some();
+    let mut replacement = payload.into_inner().into_recurring();
+
+    let mut templates = state.recurring.templates.write().await;
+    let Some(slot) = templates.iter_mut().find(|r| r.id == id) else {
+        return HttpResponse::NotFound().json(serde_json::json!({"error":"not found"}));
+    };
+    replacement.id = id;
+    if !restart {
+        replacement.start = slot.start;
+        replacement.runs = slot.runs;
+        replacement.next_run = slot.next_run;
+    }
+    let previous = std::mem::replace(slot, replacement.clone());
+    if let Err(e) = state.recurring.save(&templates).await {
+        if let Some(slot) = templates.iter_mut().find(|r| r.id == id) {
+            *slot = previous;
+        }
+        tracing::error!(error = %e, "Failed to persist recurring template");
+        return HttpResponse::InternalServerError()
+            .json(serde_json::json!({"error":"failed to save recurring transaction"}));
+    }
+
+    HttpResponse::Ok().json(replacement)
+}
+
+/// Stop a template from producing further entries; transactions it already
+/// created are left alone.
+#[utoipa::path(
+    tag = "recurring",
+    params(("id" = Uuid, Path, description = "recurring template id")),
+    responses((status = 204, description = "removed"), (status = 404, description = "not found"))
+)]
+#[delete("/recurring/{id}")]
+async fn delete_recurring(state: web::Data<AppState>, path: web::Path<String>) -> impl Responder {
+    let Ok(id) = Uuid::parse_str(&path) else {
+        return HttpResponse::BadRequest().json(serde_json::json!({"error":"invalid uuid"}));
+    };
+    let mut templates = state.recurring.templates.write().await;
+    let Some(pos) = templates.iter().position(|r| r.id == id) else {
+        return HttpResponse::NotFound().json(serde_json::json!({"error":"not found"}));
+    };
+    let removed = templates.remove(pos);
+    if let Err(e) = state.recurring.save(&templates).await {
+        templates.insert(pos, removed);
+        tracing::error!(error = %e, "Failed to persist recurring template");
+        return HttpResponse::InternalServerError()
+            .json(serde_json::json!({"error":"failed to delete recurring transaction"}));
+    }
+    HttpResponse::NoContent().finish()
+}
+
+/// The budget already covering the same user, category and currency, if any.
+fn conflicting_budget<'a>(budgets: &'a [Budget], candidate: &Budget) -> Option<&'a Budget> {
+    budgets.iter().find(|b| {
+        b.id != candidate.id
+            && b.user == candidate.user
+            && b.category == candidate.category
+            && b.currency == candidate.currency
+    })
+}
+
+#[utoipa::path(
+    tag = "budgets",
+    request_body = CreateBudget,
+    responses(
+        (status = 201, body = Budget),
+        (status = 400, description = "validation failed"),
+        (status = 409, description = "that user, category and currency already has a budget"),
+    )
+)]
+#[post("/budgets")]
+async fn create_budget(
+    state: web::Data<AppState>,
+    payload: web::Json<CreateBudget>,
+) -> impl Responder {
+    if let Err(errors) = payload.validate() {
+        return validation_failed(errors);
+    }
+    let budget = payload.into_inner().into_budget();
+
+    let mut budgets = state.budgets.budgets.write().await;
+    if let Some(existing) = conflicting_budget(&budgets, &budget) {
+        return HttpResponse::Conflict().json(serde_json::json!({
+            "error": "a budget for this user, category and currency already exists",
+            "existing": existing
+        }));
+    }
+    budgets.push(budget.clone());
+    if let Err(e) = state.budgets.save(&budgets).await {
+        budgets.pop();
+        tracing::error!(error = %e, "Failed to persist budget");
+        return HttpResponse::InternalServerError()
+            .json(serde_json::json!({"error":"failed to save budget"}));
+    }
+
+    HttpResponse::Created()
+        .insert_header((
+            header::LOCATION,
+            state.url(&format!("/budgets/{}", budget.id)),
+        ))
+        .json(budget)
+}
+
+#[utoipa::path(
+    tag = "budgets",
+    params(UserFilter),
+    responses((status = 200, description = "`{ total, items }`"))
+)]
+#[get("/budgets")]
+async fn list_budgets(state: web::Data<AppState>, user: web::Query<UserFilter>) -> impl Responder {
+    let budgets = state.budgets.budgets.read().await;
+    let items: Vec<_> = budgets
+        .iter()
+        .filter(|b| user.user.as_deref().is_none_or(|u| b.user == u.trim()))
+        .collect();
+    HttpResponse::Ok().json(serde_json::json!({"total": items.len(), "items": items}))
+}
+
+#[utoipa::path(
+    tag = "budgets",
+    params(("id" = Uuid, Path, description = "budget id")),
+    responses((status = 200, body = Budget), (status = 404, description = "not found"))
+)]
+#[get("/budgets/{id}")]
+async fn get_budget(state: web::Data<AppState>, path: web::Path<String>) -> impl Responder {
+    let Ok(id) = Uuid::parse_str(&path) else {
+        return HttpResponse::BadRequest().json(serde_json::json!({"error":"invalid uuid"}));
+    };
+    let budgets = state.budgets.budgets.read().await;
+    match budgets.iter().find(|b| b.id == id) {
+        Some(b) => HttpResponse::Ok().json(b),
+        None => HttpResponse::NotFound().json(serde_json::json!({"error":"not found"})),
+    }
+}
+
+#[utoipa::path(
+    tag = "budgets",
+    params(("id" = Uuid, Path, description = "budget id")),
+    request_body = CreateBudget,
+    responses(
+        (status = 200, body = Budget),
+        (status = 400, description = "validation failed"),
+        (status = 404, description = "not found"),
+        (status = 409, description = "that user, category and currency already has a budget"),
+    )
+)]
+#[put("/budgets/{id}")]
+async fn update_budget(
+    state: web::Data<AppState>,
+    path: web::Path<String>,
+    payload: web::Json<CreateBudget>,
+) -> impl Responder {
+    let Ok(id) = Uuid::parse_str(&path) else {
+        return HttpResponse::BadRequest().json(serde_json::json!({"error":"invalid uuid"}));
+    };
+    if let Err(errors) = payload.validate() {
+        return validation_failed(errors);
+    }
+    let mut replacement = payload.into_inner().into_budget();
+    replacement.id = id;
+
+    let mut budgets = state.budgets.budgets.write().await;
+    if let Some(existing) = conflicting_budget(&budgets, &replacement) {
+        return HttpResponse::Conflict().json(serde_json::json!({
+            "error": "a budget for this user, category and currency already exists",
+            "existing": existing
+        }));
+    }
+    let Some(slot) = budgets.iter_mut().find(|b| b.id == id) else {
+        return HttpResponse::NotFound().json(serde_json::json!({"error":"not found"}));
+    };
+    let previous = std::mem::replace(slot, replacement.clone());
+    if let Err(e) = state.budgets.save(&budgets).await {
+        if let Some(slot) = budgets.iter_mut().find(|b| b.id == id) {
+            *slot = previous;
+        }
+        tracing::error!(error = %e, "Failed to persist budget");
+        return HttpResponse::InternalServerError()
+            .json(serde_json::json!({"error":"failed to save budget"}));
+    }
+
+    HttpResponse::Ok().json(replacement)
+}
+
+#[utoipa::path(
+    tag = "budgets",
+    params(("id" = Uuid, Path, description = "budget id")),
+    responses((status = 204, description = "removed"), (status = 404, description = "not found"))
+)]
+#[delete("/budgets/{id}")]
+async fn delete_budget(state: web::Data<AppState>, path: web::Path<String>) -> impl Responder {
+    let Ok(id) = Uuid::parse_str(&path) else {
+        return HttpResponse::BadRequest().json(serde_json::json!({"error":"invalid uuid"}));
+    };
+    let mut budgets = state.budgets.budgets.write().await;
+    let Some(pos) = budgets.iter().position(|b| b.id == id) else {
+        return HttpResponse::NotFound().json(serde_json::json!({"error":"not found"}));
+    };
+    let removed = budgets.remove(pos);
+    if let Err(e) = state.budgets.save(&budgets).await {
+        budgets.insert(pos, removed);
+        tracing::error!(error = %e, "Failed to persist budget");
+        return HttpResponse::InternalServerError()
+            .json(serde_json::json!({"error":"failed to delete budget"}));
+    }
+    HttpResponse::NoContent().finish()
+}
+
+#[utoipa::path(
+    tag = "admin",
+    responses((status = 201, description = "`{ backup, transactions }`"))
+)]
+#[post("/admin/backup")]
+async fn create_backup(state: web::Data<AppState>) -> impl Responder {
+    match state.backup().await {
+        Ok((name, count)) => HttpResponse::Created().json(serde_json::json!({
+            "backup": name,
+            "transactions": count
+        })),
+        Err(e) => {
+            tracing::error!(error = %e, "Failed to write backup");
+            HttpResponse::InternalServerError()
+                .json(serde_json::json!({"error":"failed to write backup"}))
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct RestoreRequest {
+    /// file name as returned by POST /admin/backup or GET /admin/backups
+    pub backup: String,
+}
+
+/// Replace the whole ledger with a backup. The backup is parsed before anything
+/// is touched, and the current state is backed up first so a restore can itself
+/// be undone.
+#[utoipa::path(
+    tag = "admin",
+    request_body = RestoreRequest,
+    responses(
+        (status = 200, description = "`{ restored, backup, previous_backup }`"),
+        (status = 400, description = "not a backup file name"),
+        (status = 404, description = "backup not found"),
+        (status = 422, description = "backup does not parse, or reuses ids with BOOKKEEPING_STRICT_IDS set"),
+    )
+)]
+#[post("/admin/restore")]
+async fn restore_backup(
+    state: web::Data<AppState>,
+    payload: web::Json<RestoreRequest>,
+) -> impl Responder {
+    let restored = match state.backups.read(&payload.backup).await {
+        Ok(txs) => txs,
+        Err(e) => {
+            let response = match e.kind() {
+                std::io::ErrorKind::NotFound => HttpResponse::NotFound(),
+                std::io::ErrorKind::InvalidInput => HttpResponse::BadRequest(),
+                std::io::ErrorKind::InvalidData => HttpResponse::UnprocessableEntity(),
+                _ => {
+                    tracing::error!(error = %e, "Failed to read backup");
+                    HttpResponse::InternalServerError()
+                }
+            }
+            .json(serde_json::json!({
+                "error": "backup cannot be restored",
+                "detail": e.to_string()
+            }));
+            return response;
+        }
+    };
+    // the same rule as loading storage, so every restored row stays reachable by id
+    let (restored, repeated) = storage::dedupe_ids(restored);
+    if !repeated.is_empty() {
+        if state.config.strict_ids {
+            return HttpResponse::UnprocessableEntity().json(serde_json::json!({
+                "error": "backup cannot be restored",
+                "detail": format!("{} transaction ids appear more than once", repeated.len()),
+                "ids": repeated
+            }));
+        }
+        tracing::warn!(
+            ids = ?repeated,
+            backup = %payload.backup,
+            "Backup reuses ids; kept the last copy of each"
+        );
+    }
+
+    let previous_backup = match state.backup().await {
+        Ok((name, _)) => name,
+        Err(e) => {
+            tracing::error!(error = %e, "Failed to back up before restore");
+            return HttpResponse::InternalServerError().json(
+                serde_json::json!({"error":"failed to back up current state; restore aborted"}),
+            );
+        }
+    };
+
+    let count = {
+        // taken first, as flush() does, so the two can't deadlock
+        let checkpoint = state.wal.checkpoint().await;
+        // archived rows keep their numbers, as on startup
+        let first_seq = state.archive.next_seq().await;
+        let Some(mut write_guard) = state.write_ledger().await else {
+            return lock_timeout();
+        };
+        let previous =
+            std::mem::replace(&mut *write_guard, Ledger::starting_at(restored, first_seq));
+        // write through under the lock so storage and memory swap together
+        if let Err(e) = checkpoint.save(&*state.storage, &write_guard).await {
+            *write_guard = previous;
+            tracing::error!(error = %e, "Failed to persist restored backup");
+            return HttpResponse::InternalServerError()
+                .json(serde_json::json!({"error":"failed to save restored transactions"}));
+        }
+        state.last_saved.store(now_secs(), Ordering::Release);
+        // logged changes describe the old ledger and must not be replayed over this one
+        if let Err(e) = checkpoint.truncate().await {
+            tracing::error!(error = %e, "Failed to clear the write-ahead log after restore");
+        }
+        state.dirty.store(false, Ordering::Release);
+        // the steps describe a ledger that no longer exists
+        state.undo.clear();
+        write_guard.len()
+    };
+    tracing::info!(backup = %payload.backup, count, "Restored backup");
+
     HttpResponse::Ok().json(serde_json::json!({
-        "user": user,
-        "count": count,
-        "total_amount": total,
-        "transactions": user_txs
+        "restored": count,
+        "backup": payload.backup,
+        "previous_backup": previous_backup
     }))
 }
 
-#[actix_web::main]
-async fn main() -> std::io::Result<()> {
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ClosePeriodQuery {
+    /// UNIX seconds; entries dated strictly before this are locked
+    pub before: u64,
+}
+
+/// Close the books: lock every transaction dated before `before`, deleted ones
+/// included, so it can't be edited, deleted, restored or split any more.
+/// There is no unlock, and the undo history is cleared because its steps
+/// could otherwise reach back into the closed period.
+#[utoipa::path(
+    tag = "admin",
+    params(ClosePeriodQuery),
+    responses(
+        (status = 200, description = "`{ locked, before }` with the number of entries newly locked"),
+        (status = 400, description = "missing or malformed `before`"),
+    )
+)]
+#[post("/admin/close-period")]
+async fn close_period(
+    state: web::Data<AppState>,
+    query: web::Query<ClosePeriodQuery>,
+) -> impl Responder {
+    let locked = {
+        let Some(mut write_guard) = state.write_ledger().await else {
+            return lock_timeout();
+        };
+        let ids: Vec<Uuid> = write_guard
+            .iter()
+            .filter(|t| !t.locked && t.timestamp < query.before)
+            .map(|t| t.id)
+            .collect();
+        let mut locked = Vec::with_capacity(ids.len());
+        for id in &ids {
+            write_guard.update(id, |tx| {
+                tx.locked = true;
+                tx.bump_version();
+                locked.push(tx.clone());
+            });
+        }
+        if !locked.is_empty() {
+            state.undo.clear();
+        }
+        locked
+    };
+
+    if !locked.is_empty()
+        && let Err(e) = state.persist().await
+    {
+        tracing::error!(error = %e, "Failed to persist period close");
+        return persist_failed("failed to save changes");
+    }
+    state.metrics.updated.inc_by(locked.len() as u64);
+    for tx in &locked {
+        state.notify(ChangeKind::Updated, tx).await;
+    }
+    tracing::info!(before = query.before, count = locked.len(), "Closed period");
+
+    HttpResponse::Ok().json(serde_json::json!({
+        "locked": locked.len(),
+        "before": query.before
+    }))
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct DiffQuery {
+    /// UNIX seconds, inclusive; defaults to the start of the audit log
+    pub from: Option<u64>,
+    /// UNIX seconds, inclusive; defaults to now
+    pub to: Option<u64>,
+}
+
+/// Transactions created, updated and deleted between two times, replayed from
+/// the audit log, for reconciling what changed during a period.
+#[utoipa::path(
+    tag = "admin",
+    params(DiffQuery),
+    responses(
+        (status = 200, body = Diff, description = "net changes in the window, split into created, updated and deleted"),
+        (status = 400, description = "`from` is after `to`"),
+    )
+)]
+#[get("/admin/diff")]
+async fn audit_diff(state: web::Data<AppState>, query: web::Query<DiffQuery>) -> impl Responder {
+    let from = query.from.unwrap_or(0);
+    let to = query.to.unwrap_or_else(now_secs);
+    if from > to {
+        return validation_failed(vec![FieldError::new("from", "must not be after `to`")]);
+    }
+    HttpResponse::Ok().json(state.audit.diff(from, to).await)
+}
+
+/// Sanity-check the stored ledger, e.g. after editing the JSON file by hand.
+#[utoipa::path(
+    tag = "admin",
+    responses((status = 200, body = VerifyReport, description = "every problem found; `ok` is true when there are none"))
+)]
+#[get("/admin/verify")]
+async fn verify_ledger(state: web::Data<AppState>) -> impl Responder {
+    let Some(read_guard) = state.read_ledger().await else {
+        return lock_timeout();
+    };
+    let report = verify::verify(&read_guard, now_secs());
+    drop(read_guard);
+    if !report.ok {
+        tracing::warn!(
+            findings = report.findings.len(),
+            "Ledger verification found problems"
+        );
+    }
+    HttpResponse::Ok().json(report)
+}
+
+#[utoipa::path(
+    tag = "admin",
+    responses((status = 200, description = "`{ backups }`, oldest first"))
+)]
+#[get("/admin/backups")]
+async fn list_backups(state: web::Data<AppState>) -> impl Responder {
+    match state.backups.list().await {
+        Ok(names) => HttpResponse::Ok().json(serde_json::json!({"backups": names})),
+        Err(e) => {
+            tracing::error!(error = %e, "Failed to list backups");
+            HttpResponse::InternalServerError()
+                .json(serde_json::json!({"error":"failed to list backups"}))
+        }
+    }
+}
+
+/// Transactions the archive job has moved out of the live ledger, oldest
+/// archived first.
+#[utoipa::path(
+    tag = "transactions",
+    params(UserFilter),
+    responses((status = 200, description = "`{ total, items }`"))
+)]
+#[get("/archive")]
+async fn list_archive(state: web::Data<AppState>, user: web::Query<UserFilter>) -> impl Responder {
+    let user = user.into_inner().user.map(|u| u.trim().to_string());
+    let matches = move |t: &Transaction| user.as_deref().is_none_or(|u| t.user == u);
+    // the archive only grows, so positions up to `end` hold still while streaming
+    let (end, total) = {
+        let archived = state.archive.transactions.read().await;
+        (
+            archived.len(),
+            archived.iter().filter(|t| matches(t)).count(),
+        )
+    };
+    let fetch = move |start: usize| {
+        let state = state.clone();
+        let matches = matches.clone();
+        async move {
+            let archived = state.archive.transactions.read().await;
+            let mut items = Vec::new();
+            let mut position = start;
+            while position < end && items.len() < streaming::ITEMS_PER_CHUNK {
+                if matches(&archived[position]) {
+                    items.push(archived[position].clone());
+                }
+                position += 1;
+            }
+            Ok((items, position))
+        }
+    };
+    HttpResponse::Ok()
+        .content_type("application/json")
+        .streaming(streaming::json_array_pages(
+            format!("{{\"total\":{},\"items\":", total),
+            fetch,
+            "}",
+        ))
+}
+
+/// Apply one undo or redo step under the write lock, then persist and notify.
+async fn step_history(
+    state: &AppState,
+    step: fn(&UndoHistory, &mut Ledger) -> Option<undo::Applied>,
+    nothing_left: &str,
+) -> HttpResponse {
+    let applied = {
+        let Some(mut write_guard) = state.write_ledger().await else {
+            return lock_timeout();
+        };
+        match step(&state.undo, &mut write_guard) {
+            Some(applied) => applied,
+            None => {
+                return HttpResponse::Conflict().json(serde_json::json!({"error": nothing_left}));
+            }
+        }
+    };
+
+    if let Err(e) = state.persist().await {
+        tracing::error!(error = %e, "Failed to persist after undo or redo");
+        return persist_failed("failed to save changes");
+    }
+    for (kind, tx) in &applied.changes {
+        state.notify(*kind, tx).await;
+    }
+
+    let transactions: Vec<&Transaction> = applied.changes.iter().map(|(_, tx)| tx).collect();
+    HttpResponse::Ok().json(serde_json::json!({
+        "action": applied.action,
+        "transactions": transactions
+    }))
+}
+
+/// Reverse the most recent change made through the API. Created entries are
+/// removed outright; edits, deletes and restores get their previous state back
+/// under a new version.
+#[utoipa::path(
+    tag = "admin",
+    responses(
+        (status = 200, description = "`{ action, transactions }` with the affected transactions as they are now"),
+        (status = 409, description = "nothing to undo"),
+    )
+)]
+#[post("/admin/undo")]
+async fn undo_change(state: web::Data<AppState>) -> impl Responder {
+    step_history(&state, UndoHistory::undo, "nothing to undo").await
+}
+
+/// Re-apply the last undone change. Any new change clears what can be redone.
+#[utoipa::path(
+    tag = "admin",
+    responses(
+        (status = 200, description = "`{ action, transactions }` with the affected transactions as they are now"),
+        (status = 409, description = "nothing to redo"),
+    )
+)]
+#[post("/admin/redo")]
+async fn redo_change(state: web::Data<AppState>) -> impl Responder {
+    step_history(&state, UndoHistory::redo, "nothing to redo").await
+}
+
+/// Pushes a JSON message for every transaction change made after the
+/// connection opens.
+#[utoipa::path(
+    tag = "monitoring",
+    responses((status = 101, description = "switching to the WebSocket protocol"))
+)]
+#[get("/ws")]
+async fn live_updates(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    body: web::Payload,
+) -> actix_web::Result<HttpResponse> {
+    let (response, session, incoming) = actix_ws::handle(&req, body)?;
+    actix_web::rt::spawn(events::forward_to_socket(
+        session,
+        incoming,
+        state.events.subscribe(),
+    ));
+    Ok(response)
+}
+
+/// Same feed as /ws as a `text/event-stream`, for clients that only listen.
+#[utoipa::path(
+    tag = "monitoring",
+    responses((status = 200, description = "one SSE event per change, named after its change type", content_type = "text/event-stream", body = String))
+)]
+#[get("/events")]
+async fn event_stream(state: web::Data<AppState>) -> impl Responder {
+    HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header((header::CACHE_CONTROL, "no-cache"))
+        .streaming(events::sse_stream(state.events.subscribe()))
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct Stats {
+    pub uptime_secs: u64,
+    /// every stored row, soft-deleted ones included
+    pub transactions: usize,
+    pub live_transactions: usize,
+    /// size of the JSON file or SQLite database; null if it can't be read
+    pub storage_bytes: Option<u64>,
+    /// UNIX seconds of the last full save since startup; null before the first
+    pub last_saved: Option<u64>,
+    /// resident set size; only known on Linux
+    pub memory_bytes: Option<u64>,
+}
+
+/// The process's resident memory, from /proc.
+async fn resident_memory() -> Option<u64> {
+    let status = tokio::fs::read_to_string("/proc/self/status").await.ok()?;
+    let line = status.lines().find(|l| l.starts_with("VmRSS:"))?;
+    let kib: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+    Some(kib * 1024)
+}
+
+/// Quick diagnostics for whoever is looking after the service; /metrics is
+/// the place for anything that should be graphed.
+#[utoipa::path(
+    tag = "monitoring",
+    responses((status = 200, body = Stats))
+)]
+#[get("/stats")]
+async fn service_stats(state: web::Data<AppState>) -> impl Responder {
+    let (transactions, live_transactions) = {
+        let Some(read_guard) = state.read_ledger().await else {
+            return lock_timeout();
+        };
+        let live = read_guard.iter().filter(|tx| !tx.deleted).count();
+        (read_guard.len(), live)
+    };
+    let storage_bytes = tokio::fs::metadata(state.config.storage_path())
+        .await
+        .ok()
+        .map(|m| m.len());
+    let last_saved = state.last_saved.load(Ordering::Acquire);
+    HttpResponse::Ok().json(Stats {
+        uptime_secs: state.started.elapsed().as_secs(),
+        transactions,
+        live_transactions,
+        storage_bytes,
+        last_saved: (last_saved > 0).then_some(last_saved),
+        memory_bytes: resident_memory().await,
+    })
+}
+
+/// Swagger UI is mounted under /swagger/; send the bare path there.
+#[get("/swagger")]
+async fn swagger_redirect(state: web::Data<AppState>) -> impl Responder {
+    HttpResponse::PermanentRedirect()
+        .insert_header((header::LOCATION, state.url("/swagger/")))
+        .finish()
+}
+
+#[utoipa::path(
+    tag = "monitoring",
+    responses((status = 200, description = "Prometheus text format", content_type = "text/plain", body = String))
+)]
+#[get("/metrics")]
+async fn export_metrics(state: web::Data<AppState>) -> impl Responder {
+    // the gauge is cheap to derive, so refresh it at scrape time instead of in every handler
+    let Some(read_guard) = state.read_ledger().await else {
+        return lock_timeout();
+    };
+    let live = read_guard.iter().filter(|tx| !tx.deleted).count();
+    drop(read_guard);
+    state.metrics.transactions.set(live as i64);
+
+    match state.metrics.render() {
+        Ok(body) => HttpResponse::Ok()
+            .content_type(prometheus::TEXT_FORMAT)
+            .body(body),
+        Err(e) => {
+            tracing::error!(error = %e, "Failed to encode metrics");
+            HttpResponse::InternalServerError()
+                .json(serde_json::json!({"error":"failed to encode metrics"}))
+        }
+    }
+}
+
+/// Fallback for any method on a path no route matched.
+async fn route_not_found(req: HttpRequest) -> HttpResponse {
+    HttpResponse::NotFound().json(serde_json::json!({
+        "error": "route not found",
+        "path": req.path()
+    }))
+}
+
+/// 423 for a change to an entry in a closed period.
+fn locked_response(id: Uuid) -> HttpResponse {
+    HttpResponse::build(actix_web::http::StatusCode::LOCKED).json(serde_json::json!({
+        "error": "transaction is locked; its period has been closed",
+        "id": id
+    }))
+}
+
+/// 500 for a change that is applied but didn't reach storage. It stays in
+/// memory and goes out with the next successful save, so the client shouldn't
+/// simply resend it.
+fn persist_failed(error: &str) -> HttpResponse {
+    HttpResponse::InternalServerError().json(serde_json::json!({
+        "error": error,
+        "detail": "the change was applied in memory but could not be written to storage; \
+                   it will be saved with the next successful write"
+    }))
+}
+
+/// 503 for a request that gave up waiting for the transaction lock. Nothing
+/// was changed, so the client can retry.
+fn lock_timeout() -> HttpResponse {
+    HttpResponse::ServiceUnavailable()
+        .insert_header((header::RETRY_AFTER, "1"))
+        .json(serde_json::json!({
+            "error": "the server is busy; nothing was changed, try again shortly"
+        }))
+}
+
+/// The io::Error saves report when the lock couldn't be had in time.
+fn lock_timed_out() -> std::io::Error {
+    std::io::Error::new(
+        std::io::ErrorKind::TimedOut,
+        "timed out waiting for the transaction lock",
+    )
+}
+
+/// Structured 413 that tells the client what the cap is.
+fn payload_too_large(limit: usize) -> HttpResponse {
+    HttpResponse::PayloadTooLarge().json(serde_json::json!({
+        "error": "payload too large",
+        "detail": format!("request body exceeds the {} byte limit", limit),
+        "limit_bytes": limit
+    }))
+}
+
+/// Replace actix's terse plain-text JSON errors with the structured bodies the handlers use.
+fn json_error_handler(err: JsonPayloadError, limit: usize) -> actix_web::Error {
+    let response = match &err {
+        JsonPayloadError::Overflow { .. } | JsonPayloadError::OverflowKnownLength { .. } => {
+            payload_too_large(limit)
+        }
+        JsonPayloadError::ContentType => {
+            HttpResponse::UnsupportedMediaType().json(serde_json::json!({
+                "error": "expected a JSON body",
+                "detail": "Content-Type must be application/json"
+            }))
+        }
+        // serde's message carries the line/column of the problem
+        JsonPayloadError::Deserialize(e) => HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "invalid JSON",
+            "detail": e.to_string()
+        })),
+        other => HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "invalid JSON",
+            "detail": other.to_string()
+        })),
+    };
+    InternalError::from_response(err, response).into()
+}
+
+/// Turn query-string parse failures into the same JSON error shape the handlers use.
+fn query_error_handler(err: QueryPayloadError, _req: &HttpRequest) -> actix_web::Error {
+    let response = HttpResponse::BadRequest().json(serde_json::json!({
+        "error": "invalid query parameters",
+        "detail": err.to_string()
+    }));
+    InternalError::from_response(err, response).into()
+}
+
+/// Open storage and every side file named in `config`, replaying anything
+/// the write-ahead log still holds.
+async fn load_state(config: Config) -> std::io::Result<AppState> {
+    let wal = WriteAheadLog::new(&config.wal_file);
+    let rates = RateTable::load(&config.rates_file).await?;
+    tracing::info!(file = %config.rates_file, currencies = rates.len(), "Loaded exchange rates");
+    let storage: Box<dyn Storage + Send + Sync> = match &config.storage {
+        StorageBackend::Json => Box::new(
+            JsonFileStorage::new(&config.storage_file)
+                .quarantine_corrupt(config.quarantine_corrupt)
+                .pretty(config.pretty_json),
+        ),
+        StorageBackend::Sqlite { db_path } => Box::new(SqliteStorage::open(db_path)?),
+    };
+
     // Load existing transactions from disk
-    let initial = AppState::load(STORAGE_FILE).await.unwrap_or_default();
+    let initial = storage.load().await.inspect_err(|e| {
+        tracing::error!(
+            error = %e,
+            "Refusing to start: stored transactions could not be loaded \
+             (set BOOKKEEPING_QUARANTINE_CORRUPT=true to move a corrupt JSON file aside)"
+        );
+    })?;
+    let (initial, repeated) = storage::dedupe_ids(initial);
+    if !repeated.is_empty() {
+        if config.strict_ids {
+            tracing::error!(
+                ids = ?repeated,
+                "Refusing to start: stored transactions reuse ids \
+                 (unset BOOKKEEPING_STRICT_IDS to keep the last copy of each)"
+            );
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("{} transaction ids appear more than once", repeated.len()),
+            ));
+        }
+        tracing::warn!(
+            ids = ?repeated,
+            "Stored transactions reuse ids; kept the last copy of each"
+        );
+    }
+
+    let archive = Archive::load(&config.archive_file).await?;
+    // archived rows keep their numbers, so new ones must start past them too
+    let first_seq = archive.next_seq().await;
+    let mut ledger = Ledger::starting_at(initial, first_seq);
+    // anything still in the log was acknowledged but never reached storage
+    let replayed = wal::replay(&mut ledger, wal.read().await?);
+    let checkpoint = wal.checkpoint().await;
+    if replayed > 0 {
+        checkpoint.save(&*storage, &ledger).await?;
+        tracing::warn!(
+            count = replayed,
+            file = %wal.file_path(),
+            "Replayed changes from the write-ahead log"
+        );
+    }
+    checkpoint.truncate().await?;
+    ledger.take_changed();
 
     let state = AppState {
-        transactions: Arc::new(RwLock::new(initial)),
-        file_path: STORAGE_FILE.to_string(),
+        transactions: Arc::new(RwLock::new(ledger)),
+        storage,
+        wal,
+        dirty: AtomicBool::new(false),
+        metrics: Metrics::new().map_err(std::io::Error::other)?,
+        recurring: RecurringStore::load(&config.recurring_file).await?,
+        budgets: BudgetStore::load(&config.budgets_file).await?,
+        idempotency: IdempotencyCache::new(config.idempotency_ttl),
+        backups: Backups::new(&config.backup_dir, config.backup_retention),
+        rates,
+        events: ChangeFeed::new(),
+        undo: UndoHistory::new(config.undo_history),
+        audit: AuditLog::load(&config.audit_file).await?,
+        archive,
+        receipts: Receipts::new(&config.receipts_dir, config.max_receipt_bytes),
+        config,
+        started: Instant::now(),
+        last_saved: AtomicU64::new(0),
     };
+    Ok(state)
+}
+
+#[actix_web::main]
+async fn main() -> std::io::Result<()> {
+    let config = Config::load(std::env::args().skip(1))?;
+    // RUST_LOG overrides the profile's verbosity, e.g. RUST_LOG=debug or RUST_LOG=myday=warn
+    tracing_subscriber::fmt()
+        .with_env_filter(
+            EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(config.log_filter)),
+        )
+        .init();
+    tracing::info!(?config, "Effective configuration");
+
+    if let Some(currency) = &config.default_currency {
+        let _ = CONFIGURED_CURRENCY.set(currency.clone());
+    }
+    let api_key = config
+        .api_key
+        .as_ref()
+        .map(|k| web::Data::new(ApiKey(k.expose().to_string())));
+    let rate_limiter = (config.rate_limit_per_minute > 0)
+        .then(|| web::Data::new(RateLimiter::per_minute(config.rate_limit_per_minute)));
+    let cors_origins = config.cors_origins.clone();
+    let state = load_state(config).await?;
 
     let shared = web::Data::new(state);
+    let final_state = shared.clone();
+    let config = &final_state.config;
+
+    if let Some(interval) = config.flush_interval {
+        tokio::spawn(run_flusher(shared.clone(), interval));
+    }
+    tokio::spawn(run_idempotency_prune(shared.clone()));
+    if config.backup_interval_secs > 0 {
+        tokio::spawn(run_backups(
+            shared.clone(),
+            Duration::from_secs(config.backup_interval_secs),
+        ));
+    }
+    // both jobs change the ledger, so read-only mode leaves them off
+    if config.recurring_check_secs > 0 && !config.readonly {
+        tokio::spawn(run_recurring(
+            shared.clone(),
+            Duration::from_secs(config.recurring_check_secs),
+        ));
+    }
+
+    if config.archive_after_days > 0 && !config.readonly {
+        tokio::spawn(run_archive(shared.clone(), config.archive_after_days));
+    }
+
+    if let Some(limiter) = &rate_limiter {
+        tokio::spawn(middleware::run_bucket_cleanup(
+            limiter.clone(),
+            RATE_LIMIT_CLEANUP_INTERVAL,
+        ));
+    }
+
+    match &cors_origins {
+        None => {
+            tracing::warn!("CORS allows any origin; set BOOKKEEPING_CORS_ORIGINS to restrict it")
+        }
+        Some(origins) => tracing::info!(?origins, "CORS origins"),
+    }
+    if api_key.is_none() {
+        tracing::warn!("BOOKKEEPING_API_KEY is unset; API authentication is disabled");
+    }
+    let read_only = config.readonly.then(|| web::Data::new(ReadOnly));
+    if read_only.is_some() {
+        tracing::warn!("BOOKKEEPING_READONLY is set; all changes will be refused");
+    }
+    tracing::info!(
+        "Server running at http://{}:{}{}",
+        config.host,
+        config.port,
+        config.base_path
+    );
+    let max_body_bytes = config.max_body_bytes;
+    let base_path = config.base_path.clone();
 
-    println!("Server running at http://127.0.0.1:3000");
-
-    HttpServer::new(move || {
-        App::new()
-            .app_data(shared.clone())
-            .service(create_transaction)
-            .service(list_transactions)
-            .service(get_transaction)
-            .service(update_transaction)
-            .service(delete_transaction)
-            .service(user_summary)
+    let server = HttpServer::new(move || {
+        let mut app = App::new().app_data(shared.clone());
+        if let Some(key) = &api_key {
+            app = app.app_data(key.clone());
+        }
+        if let Some(limiter) = &rate_limiter {
+            app = app.app_data(limiter.clone());
+        }
+        if let Some(read_only) = &read_only {
+            app = app.app_data(read_only.clone());
+        }
+        // later wraps run first: every request gets an id, is logged, CORS preflights are
+        // answered before auth (browsers never send credentials on them), and
+        // floods are turned away before auth. Writes in read-only mode are
+        // refused only once the caller is known to be allowed in. Compress
+        // encodes whatever the handler returns per Accept-Encoding, after the
+        // innermost envelope has wrapped it for clients that asked.
+        app.wrap(actix_web::middleware::from_fn(middleware::envelope))
+            .wrap(actix_web::middleware::Compress::default())
+            .wrap(actix_web::middleware::from_fn(middleware::reject_writes))
+            .wrap(actix_web::middleware::from_fn(middleware::require_api_key))
+            .wrap(actix_web::middleware::from_fn(middleware::rate_limit))
+            .wrap(middleware::cors(cors_origins.as_deref()))
+            .wrap(actix_web::middleware::from_fn(middleware::log_request))
+            .wrap(actix_web::middleware::from_fn(middleware::request_id))
+            .app_data(web::QueryConfig::default().error_handler(query_error_handler))
+            .app_data(
+                web::JsonConfig::default()
+                    .limit(max_body_bytes)
+                    .error_handler(move |err, _req| json_error_handler(err, max_body_bytes)),
+            )
+            // ahead of the scope, which would otherwise answer everything under the base path
+            .service(
+                SwaggerUi::new(format!("{}/swagger/{{_:.*}}", base_path)).url(
+                    format!("{}/api-docs/openapi.json", base_path),
+                    openapi::document(&base_path),
+                ),
+            )
+            .service(
+                web::scope(&base_path)
+                    .service(create_transaction)
+                    .service(create_transactions_batch)
+                    .service(import_transactions)
+                    .service(list_transactions)
+                    // must be registered before the /transactions/{id} route
+                    .service(count_transactions)
+                    .service(latest_transactions)
+                    .service(search_transactions)
+                    .service(export_csv)
+                    .service(export_json)
+                    .service(get_transaction_by_seq)
+                    .service(get_transaction)
+                    .service(update_transaction)
+                    .service(patch_transaction)
+                    .service(delete_transaction)
+                    .service(restore_transaction)
+                    .service(clone_transaction)
+                    .service(split_transaction)
+                    .service(create_transfer)
+                    .service(upload_receipt)
+                    .service(get_receipt)
+                    .service(transaction_history)
+                    .service(bulk_delete_transactions)
+                    .service(bulk_update_transactions)
+                    .service(user_summary)
+                    .service(merge_user)
+                    .service(list_users)
+                    .service(list_items)
+                    .service(list_categories)
+                    .service(user_statement)
+                    .service(report_summary)
+                    .service(report_timeseries)
+                    .service(report_top_items)
+                    .service(report_top_users)
+                    .service(create_recurring)
+                    .service(list_recurring)
+                    .service(get_recurring)
+                    .service(update_recurring)
+                    .service(delete_recurring)
+                    .service(create_budget)
+                    .service(list_budgets)
+                    .service(get_budget)
+                    .service(update_budget)
+                    .service(delete_budget)
+                    .service(create_backup)
+                    .service(list_backups)
+                    .service(restore_backup)
+                    .service(verify_ledger)
+                    .service(close_period)
+                    .service(audit_diff)
+                    .service(list_archive)
+                    .service(undo_change)
+                    .service(redo_change)
+                    .service(export_metrics)
+                    .service(service_stats)
+                    .service(live_updates)
+                    .service(event_stream)
+                    .service(swagger_redirect),
+            )
+            .default_service(web::to(route_not_found))
     })
-    .bind(("127.0.0.1", 3000))?
-    .run()
-    .await
+    // we handle signals ourselves so the final flush runs after requests drain
+    .disable_signals()
+    .bind((config.host.as_str(), config.port))?
+    .run();
+
+    let handle = server.handle();
+    tokio::spawn(async move {
+        shutdown_signal().await;
+        tracing::info!("Shutdown signal received, draining requests");
+        handle.stop(true).await;
+    });
+
+    server.await?;
+    shutdown(&final_state).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{App, test as actix_test};
+    use std::path::{Path, PathBuf};
+    use std::rc::Rc;
+
+    /// A fresh directory under the system temp dir for one test's files.
+    fn scratch_dir() -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("myday-test-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// The default configuration with every file inside `dir`.
+    fn config_in(dir: &Path) -> Config {
+        let path = |name: &str| dir.join(name).to_string_lossy().into_owned();
+        let mut config = Config::load(std::iter::empty()).unwrap();
+        config.storage = StorageBackend::Json;
+        config.storage_file = path("transactions.json");
+        config.wal_file = path("transactions.wal");
+        config.recurring_file = path("recurring.json");
+        config.budgets_file = path("budgets.json");
+        config.audit_file = path("audit.jsonl");
+        config.archive_file = path("archive.json");
+        config.rates_file = path("rates.json");
+        config.backup_dir = path("backups");
+        config.receipts_dir = path("receipts");
+        config.duplicate_window_secs = 0;
+        config
+    }
+
+    fn stored(dir: &Path) -> Vec<Transaction> {
+        let data = std::fs::read_to_string(dir.join("transactions.json")).unwrap();
+        serde_json::from_str(&data).unwrap()
+    }
+
+    /// Fire `count` creates at once and wait for all of them.
+    async fn create_concurrently(state: &web::Data<AppState>, count: usize) {
+        let app = Rc::new(
+            actix_test::init_service(
+                App::new()
+                    .app_data(state.clone())
+                    .service(create_transaction),
+            )
+            .await,
+        );
+        let requests: Vec<_> = (0..count)
+            .map(|n| {
+                let app = app.clone();
+                actix_web::rt::spawn(async move {
+                    let req = actix_test::TestRequest::post()
+                        .uri("/transactions")
+                        .set_json(serde_json::json!({"user": "burst", "item": format!("i{}", n), "amount": 1}))
+                        .to_request();
+                    actix_test::call_service(&*app, req).await.status()
+                })
+            })
+            .collect();
+        for request in requests {
+            assert_eq!(request.await.unwrap(), actix_web::http::StatusCode::CREATED);
+        }
+    }
+
+    fn assert_all_created(txs: &[Transaction], count: usize) {
+        assert_eq!(txs.len(), count);
+        let ids: HashSet<Uuid> = txs.iter().map(|t| t.id).collect();
+        let seqs: HashSet<u64> = txs.iter().map(|t| t.seq).collect();
+        let items: HashSet<&str> = txs.iter().map(|t| t.item.as_str()).collect();
+        assert_eq!(ids.len(), count);
+        assert_eq!(seqs.len(), count);
+        assert_eq!(items.len(), count);
+    }
+
+    fn sample(user: &str, item: &str, amount: i64, timestamp: u64) -> Transaction {
+        serde_json::from_value(serde_json::json!({
+            "id": Uuid::new_v4(),
+            "user": user,
+            "item": item,
+            "amount": amount,
+            "timestamp": timestamp,
+        }))
+        .unwrap()
+    }
+
+    /// alice/bob rows across three days, one tagged, one categorized, one deleted;
+    /// purchases positive and income negative, as `EntryKind` stores them
+    fn ledger() -> Vec<Transaction> {
+        let mut rent = sample("alice", "Rent", 900, 100);
+        rent.category = Some("housing".into());
+        let mut coffee = sample("alice", "Coffee", 4, 200);
+        coffee.tags = vec!["treat".into()];
+        let salary = sample("bob", "Salary", -2000, 300);
+        let mut refund = sample("bob", "Refund", -15, 200);
+        refund.deleted = true;
+        vec![rent, coffee, salary, refund]
+    }
+
+    fn items<'a>(filter: &'a TransactionFilter, txs: &'a [Transaction]) -> Vec<&'a str> {
+        filter.apply_filter(txs).map(|t| t.item.as_str()).collect()
+    }
+
+    #[test]
+    fn empty_filter_hides_only_deleted_rows() {
+        let txs = ledger();
+        let filter = TransactionFilter::default();
+        assert_eq!(items(&filter, &txs), ["Rent", "Coffee", "Salary"]);
+        let filter = TransactionFilter {
+            include_deleted: true,
+            ..Default::default()
+        };
+        assert_eq!(items(&filter, &txs), ["Rent", "Coffee", "Salary", "Refund"]);
+    }
+
+    #[test]
+    fn filter_by_time_range_is_inclusive() {
+        let txs = ledger();
+        let from = TransactionFilter {
+            from: Some(200),
+            ..Default::default()
+        };
+        assert_eq!(items(&from, &txs), ["Coffee", "Salary"]);
+        let to = TransactionFilter {
+            to: Some(200),
+            ..Default::default()
+        };
+        assert_eq!(items(&to, &txs), ["Rent", "Coffee"]);
+        let both = TransactionFilter {
+            from: Some(150),
+            to: Some(250),
+            ..Default::default()
+        };
+        assert_eq!(items(&both, &txs), ["Coffee"]);
+    }
+
+    #[test]
+    fn filter_by_user_trims_and_matches_exactly() {
+        let txs = ledger();
+        let filter = TransactionFilter {
+            user: Some(" bob ".into()),
+            ..Default::default()
+        };
+        assert_eq!(items(&filter, &txs), ["Salary"]);
+        let filter = TransactionFilter {
+            user: Some("ali".into()),
+            ..Default::default()
+        };
+        assert!(items(&filter, &txs).is_empty());
+    }
+
+    #[test]
+    fn filter_by_category() {
+        let txs = ledger();
+        let filter = TransactionFilter {
+            category: Some("housing".into()),
+            ..Default::default()
+        };
+        assert_eq!(items(&filter, &txs), ["Rent"]);
+    }
+
+    #[test]
+    fn filter_by_tag_ignores_case() {
+        let txs = ledger();
+        let filter = TransactionFilter {
+            tag: Some("TREAT".into()),
+            ..Default::default()
+        };
+        assert_eq!(items(&filter, &txs), ["Coffee"]);
+    }
+
+    #[test]
+    fn filter_by_amount_range_uses_the_signed_amount() {
+        let txs = ledger();
+        let min = TransactionFilter {
+            min_amount: Some(Decimal::from(4)),
+            ..Default::default()
+        };
+        assert_eq!(items(&min, &txs), ["Rent", "Coffee"]);
+        let max = TransactionFilter {
+            max_amount: Some(Decimal::from(4)),
+            ..Default::default()
+        };
+        assert_eq!(items(&max, &txs), ["Coffee", "Salary"]);
+        let inverted = TransactionFilter {
+            min_amount: Some(Decimal::from(10)),
+            max_amount: Some(Decimal::from(5)),
+            ..Default::default()
+        };
+        assert!(inverted.validate().is_err());
+    }
+
+    #[test]
+    fn filters_combine_with_and() {
+        let txs = ledger();
+        let filter = TransactionFilter {
+            user: Some("alice".into()),
+            from: Some(150),
+            min_amount: Some(Decimal::ZERO),
+            ..Default::default()
+        };
+        assert_eq!(items(&filter, &txs), ["Coffee"]);
+        let filter = TransactionFilter {
+            user: Some("bob".into()),
+            to: Some(250),
+            include_deleted: true,
+            ..Default::default()
+        };
+        assert_eq!(items(&filter, &txs), ["Refund"]);
+    }
+
+    #[test]
+    fn filter_by_field_presence() {
+        let mut txs = ledger();
+        txs[1].note = Some("with friends".into());
+        txs[1].attachment_url = Some("https://example.com/coffee.pdf".into());
+        txs[2].receipt = Some("receipts/salary.pdf".into());
+        let flags = |set: fn(&mut TransactionFilter, bool), has: bool| {
+            let mut filter = TransactionFilter::default();
+            set(&mut filter, has);
+            items(&filter, &txs)
+                .into_iter()
+                .map(str::to_string)
+                .collect::<Vec<_>>()
+        };
+        let category = |f: &mut TransactionFilter, has| f.has_category = Some(has);
+        assert_eq!(flags(category, true), ["Rent"]);
+        assert_eq!(flags(category, false), ["Coffee", "Salary"]);
+        let note = |f: &mut TransactionFilter, has| f.has_note = Some(has);
+        assert_eq!(flags(note, true), ["Coffee"]);
+        assert_eq!(flags(note, false), ["Rent", "Salary"]);
+        let tags = |f: &mut TransactionFilter, has| f.has_tags = Some(has);
+        assert_eq!(flags(tags, true), ["Coffee"]);
+        assert_eq!(flags(tags, false), ["Rent", "Salary"]);
+        let attachment = |f: &mut TransactionFilter, has| f.has_attachment = Some(has);
+        assert_eq!(flags(attachment, true), ["Coffee"]);
+        assert_eq!(flags(attachment, false), ["Rent", "Salary"]);
+        let receipt = |f: &mut TransactionFilter, has| f.has_receipt = Some(has);
+        assert_eq!(flags(receipt, true), ["Salary"]);
+        assert_eq!(flags(receipt, false), ["Rent", "Coffee"]);
+    }
+
+    #[test]
+    fn presence_filters_reject_contradictions() {
+        let filter = TransactionFilter {
+            category: Some("housing".into()),
+            has_category: Some(false),
+            ..Default::default()
+        };
+        assert!(filter.validate().is_err());
+        let filter = TransactionFilter {
+            tag: Some("treat".into()),
+            has_tags: Some(false),
+            ..Default::default()
+        };
+        assert!(filter.validate().is_err());
+        let filter = TransactionFilter {
+            category: Some("housing".into()),
+            has_category: Some(true),
+            ..Default::default()
+        };
+        assert!(filter.validate().is_ok());
+    }
+
+    #[test]
+    fn search_matches_item_or_user_case_insensitively() {
+        let txs = ledger();
+        let hits = |needle: &str| -> Vec<&str> {
+            txs.iter()
+                .filter(|t| matches_search(t, needle))
+                .map(|t| t.item.as_str())
+                .collect()
+        };
+        assert_eq!(hits("coff"), ["Coffee"]);
+        assert_eq!(hits("bob"), ["Salary", "Refund"]);
+        assert!(hits("groceries").is_empty());
+    }
+
+    #[test]
+    fn csv_import_signs_amounts_by_kind_and_rejects_negatives() {
+        let body = "user,item,amount,kind\n\
+            a,Coffee,4.50,\n\
+            a,Refund,10,credit\n\
+            a,Typo,-3,\n\
+            a,Odd,-3,credit\n";
+        let (accepted, errors) = parse_csv_import(body.as_bytes()).unwrap();
+        let amounts: Vec<(&str, Decimal)> = accepted
+            .iter()
+            .map(|t| (t.item.as_str(), t.amount))
+            .collect();
+        assert_eq!(
+            amounts,
+            [
+                ("Coffee", Decimal::new(450, 2)),
+                ("Refund", Decimal::from(-10))
+            ]
+        );
+        let lines: Vec<u64> = errors.iter().map(|e| e.line).collect();
+        assert_eq!(lines, [4, 5]);
+        assert!(errors[0].error.contains("non-negative"));
+
+        // files without a kind column are all debits
+        let (accepted, errors) = parse_csv_import(b"user,item,amount\nb,Tea,2\n").unwrap();
+        assert!(errors.is_empty());
+        assert_eq!(accepted[0].amount, Decimal::from(2));
+    }
+
+    #[test]
+    fn csv_import_reads_amounts_as_leniently_as_json() {
+        let body = "user,item,amount\nb,Rent,\"$1,234.50\"\nb,Bad,\"12,50\"\nb,Tea, 2 \n";
+        let (accepted, errors) = parse_csv_import(body.as_bytes()).unwrap();
+        let amounts: Vec<Decimal> = accepted.iter().map(|t| t.amount).collect();
+        assert_eq!(amounts, [Decimal::new(123450, 2), Decimal::from(2)]);
+        assert_eq!(errors.len(), 1);
+        assert!(
+            errors[0].error.contains("invalid amount"),
+            "{}",
+            errors[0].error
+        );
+    }
+
+    #[test]
+    fn fold_item_ignores_case_and_spacing() {
+        assert_eq!(fold_item("Coffee"), "coffee");
+        assert_eq!(fold_item("COFFEE"), fold_item("coffee"));
+        assert_eq!(fold_item("  coffee \t"), "coffee");
+        assert_eq!(fold_item("flat   white"), "flat white");
+        assert_eq!(fold_item(" Flat\t\nWhite "), "flat white");
+        assert_ne!(fold_item("flatwhite"), fold_item("flat white"));
+    }
+
+    #[test]
+    fn top_items_fold_spellings_together_under_the_first_seen() {
+        let txs = vec![
+            sample("a", "Flat White", 4, 1),
+            sample("a", " flat  white", 3, 2),
+            sample("a", "FLAT WHITE ", 2, 3),
+            sample("a", "Tea", 1, 4),
+        ];
+        let folded = top_n(&txs, |t| &t.item, true, 10);
+        assert_eq!(folded.len(), 2);
+        let flat_white = folded.iter().find(|r| r.name == "Flat White").unwrap();
+        assert_eq!((flat_white.total, flat_white.count), (Decimal::from(9), 3));
+
+        let unfolded = top_n(&txs, |t| &t.item, false, 10);
+        assert_eq!(unfolded.len(), 4);
+    }
+
+    #[actix_web::test]
+    async fn strict_ids_refuses_to_start_on_duplicate_ids() {
+        let dir = scratch_dir();
+        let id = Uuid::new_v4();
+        let row =
+            serde_json::json!({"id": id, "user": "u", "item": "x", "amount": 1, "timestamp": 1});
+        std::fs::write(
+            dir.join("transactions.json"),
+            serde_json::json!([row, row]).to_string(),
+        )
+        .unwrap();
+
+        let lenient = load_state(config_in(&dir)).await.unwrap();
+        assert_eq!(lenient.transactions.read().await.len(), 1);
+        drop(lenient);
+
+        let mut strict = config_in(&dir);
+        strict.strict_ids = true;
+        let err = load_state(strict).await.err().unwrap();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[actix_web::test]
+    async fn restore_keeps_archived_seqs_and_drops_repeated_ids() {
+        let dir = scratch_dir();
+        let mut archived = sample("u", "old", 1, 1);
+        archived.seq = 10;
+        std::fs::write(
+            dir.join("archive.json"),
+            serde_json::to_string(&[archived]).unwrap(),
+        )
+        .unwrap();
+        let state = web::Data::new(load_state(config_in(&dir)).await.unwrap());
+        let mut first = sample("u", "first", 1, 2);
+        first.seq = 1;
+        let mut repeat = first.clone();
+        repeat.item = "repeat".into();
+        let mut second = sample("u", "second", 1, 3);
+        second.seq = 2;
+        let backup = state
+            .backups
+            .create(&[first.clone(), second, repeat])
+            .await
+            .unwrap();
+        let app = actix_test::init_service(
+            App::new()
+                .app_data(state.clone())
+                .service(restore_backup)
+                .service(create_transaction),
+        )
+        .await;
+
+        let restore = actix_test::TestRequest::post()
+            .uri("/admin/restore")
+            .set_json(serde_json::json!({"backup": backup}))
+            .to_request();
+        let res = actix_test::call_service(&app, restore).await;
+        assert_eq!(res.status(), actix_web::http::StatusCode::OK);
+        {
+            let ledger = state.transactions.read().await;
+            assert_eq!(ledger.len(), 2);
+            assert_eq!(ledger.get(&first.id).unwrap().item, "repeat");
+        }
+
+        let create = actix_test::TestRequest::post()
+            .uri("/transactions")
+            .set_json(serde_json::json!({"user": "u", "item": "new", "amount": 1}))
+            .to_request();
+        let created: Transaction = actix_test::call_and_read_body_json(&app, create).await;
+        assert_eq!(created.seq, 11);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    async fn split(state: &web::Data<AppState>, id: Uuid) -> actix_web::dev::ServiceResponse {
+        let app = actix_test::init_service(
+            App::new()
+                .app_data(state.clone())
+                .service(split_transaction),
+        )
+        .await;
+        let request = actix_test::TestRequest::post()
+            .uri(&format!("/transactions/{}/split", id))
+            .set_json(serde_json::json!([
+                {"item": "a", "amount": 6},
+                {"item": "b", "amount": 4}
+            ]))
+            .to_request();
+        actix_test::call_service(&app, request).await
+    }
+
+    #[actix_web::test]
+    async fn split_refuses_transfer_legs() {
+        let dir = scratch_dir();
+        let mut leg = sample("u", "to savings", 10, 1);
+        leg.transfer_id = Some(Uuid::new_v4());
+        std::fs::write(
+            dir.join("transactions.json"),
+            serde_json::to_string(&[leg.clone()]).unwrap(),
+        )
+        .unwrap();
+        let state = web::Data::new(load_state(config_in(&dir)).await.unwrap());
+
+        let res = split(&state, leg.id).await;
+        assert_eq!(res.status(), actix_web::http::StatusCode::CONFLICT);
+        let ledger = state.transactions.read().await;
+        assert_eq!(ledger.len(), 1);
+        assert!(!ledger.get(&leg.id).unwrap().deleted);
+        drop(ledger);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[actix_web::test]
+    async fn split_parts_leave_the_receipt_with_the_original() {
+        let dir = scratch_dir();
+        let mut source = sample("u", "groceries", 10, 1);
+        source.receipt = Some("receipt.png".into());
+        std::fs::write(
+            dir.join("transactions.json"),
+            serde_json::to_string(&[source.clone()]).unwrap(),
+        )
+        .unwrap();
+        let state = web::Data::new(load_state(config_in(&dir)).await.unwrap());
+
+        let res = split(&state, source.id).await;
+        assert_eq!(res.status(), actix_web::http::StatusCode::CREATED);
+        let parts: Vec<Transaction> = actix_test::read_body_json(res).await;
+        assert_eq!(parts.len(), 2);
+        for part in &parts {
+            assert_eq!(part.receipt, None);
+            assert_eq!(part.transfer_id, None);
+            assert!(!part.locked);
+        }
+        let ledger = state.transactions.read().await;
+        assert_eq!(
+            ledger.get(&source.id).unwrap().receipt.as_deref(),
+            Some("receipt.png")
+        );
+        drop(ledger);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[actix_web::test]
+    async fn background_writes_give_up_on_a_stuck_lock() {
+        let dir = scratch_dir();
+        let mut config = config_in(&dir);
+        config.lock_timeout = Some(Duration::from_millis(20));
+        let state = load_state(config).await.unwrap();
+        let stuck = state.transactions.write().await;
+
+        let err = archive_older_than(&state, u64::MAX).await.unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::TimedOut);
+        let err = shutdown(&state).await.unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::TimedOut);
+        drop(stuck);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[actix_web::test]
+    async fn archiving_moves_old_rows_out_of_storage() {
+        let dir = scratch_dir();
+        let old = sample("u", "old", 1, 100);
+        let new = sample("u", "new", 1, 300);
+        std::fs::write(
+            dir.join("transactions.json"),
+            serde_json::to_string(&[old.clone(), new.clone()]).unwrap(),
+        )
+        .unwrap();
+        let mut config = config_in(&dir);
+        config.flush_interval = None;
+        let state = load_state(config).await.unwrap();
+
+        assert_eq!(archive_older_than(&state, 200).await.unwrap(), 1);
+        assert_eq!(archive_older_than(&state, 200).await.unwrap(), 0);
+        let stored: Vec<Uuid> = stored(&dir).iter().map(|t| t.id).collect();
+        assert_eq!(stored, [new.id]);
+        let archived: Vec<Transaction> =
+            serde_json::from_str(&std::fs::read_to_string(dir.join("archive.json")).unwrap())
+                .unwrap();
+        assert_eq!(archived.len(), 1);
+        assert_eq!((archived[0].id, archived[0].seq), (old.id, 1));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    fn etag(res: &actix_web::dev::ServiceResponse) -> String {
+        res.headers()
+            .get(header::ETAG)
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .to_string()
+    }
+
+    #[actix_web::test]
+    async fn collection_writes_refuse_a_stale_if_match() {
+        let dir = scratch_dir();
+        let state = web::Data::new(load_state(config_in(&dir)).await.unwrap());
+        let app = actix_test::init_service(
+            App::new()
+                .app_data(state.clone())
+                .service(create_transaction)
+                .service(list_transactions),
+        )
+        .await;
+        let list = actix_test::TestRequest::get()
+            .uri("/transactions")
+            .to_request();
+        let before = etag(&actix_test::call_service(&app, list).await);
+        let create = |tag: &str| {
+            actix_test::TestRequest::post()
+                .uri("/transactions")
+                .insert_header((header::IF_MATCH, tag.to_string()))
+                .set_json(serde_json::json!({"user": "a", "item": "x", "amount": 1}))
+                .to_request()
+        };
+
+        let res = actix_test::call_service(&app, create(&before)).await;
+        assert_eq!(res.status(), actix_web::http::StatusCode::CREATED);
+        let res = actix_test::call_service(&app, create(&before)).await;
+        assert_eq!(res.status(), actix_web::http::StatusCode::CONFLICT);
+        assert_ne!(etag(&res), before);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[actix_web::test]
+    async fn item_writes_check_if_match_against_the_row_version() {
+        let dir = scratch_dir();
+        let state = web::Data::new(load_state(config_in(&dir)).await.unwrap());
+        let app = actix_test::init_service(
+            App::new()
+                .app_data(state.clone())
+                .service(create_transaction)
+                .service(get_transaction)
+                .service(delete_transaction)
+                .service(restore_transaction)
+                .service(clone_transaction),
+        )
+        .await;
+        let create = || {
+            actix_test::TestRequest::post()
+                .uri("/transactions")
+                .set_json(serde_json::json!({"user": "a", "item": "x", "amount": 1}))
+                .to_request()
+        };
+        let send = |method: &str, uri: &str, tag: &str| {
+            actix_test::TestRequest::default()
+                .method(method.parse().unwrap())
+                .uri(uri)
+                .insert_header((header::IF_MATCH, tag.to_string()))
+                .to_request()
+        };
+        let created: Transaction = actix_test::call_and_read_body_json(&app, create()).await;
+        let path = format!("/transactions/{}", created.id);
+        let restore = format!("{}/restore", path);
+        let clone = format!("{}/clone", path);
+        let get = || actix_test::TestRequest::get().uri(&path).to_request();
+
+        let tag = etag(&actix_test::call_service(&app, get()).await);
+        // an unrelated write moves the collection tag but not this row's
+        actix_test::call_service(&app, create()).await;
+
+        let stale = format!("\"{}\"", created.version + 1);
+        let res = actix_test::call_service(&app, send("DELETE", &path, &stale)).await;
+        assert_eq!(res.status(), actix_web::http::StatusCode::CONFLICT);
+        let res = actix_test::call_service(&app, send("DELETE", &path, &tag)).await;
+        assert_eq!(res.status(), actix_web::http::StatusCode::NO_CONTENT);
+
+        // the delete bumped the version, so the old tag no longer matches
+        let res = actix_test::call_service(&app, send("POST", &restore, &tag)).await;
+        assert_eq!(res.status(), actix_web::http::StatusCode::CONFLICT);
+        let tag = etag(&actix_test::call_service(&app, get()).await);
+        let res = actix_test::call_service(&app, send("POST", &restore, &tag)).await;
+        assert_eq!(res.status(), actix_web::http::StatusCode::OK);
+
+        let res = actix_test::call_service(&app, send("POST", &clone, &tag)).await;
+        assert_eq!(res.status(), actix_web::http::StatusCode::CONFLICT);
+        let tag = etag(&actix_test::call_service(&app, get()).await);
+        let res = actix_test::call_service(&app, send("POST", &clone, &tag)).await;
+        assert_eq!(res.status(), actix_web::http::StatusCode::CREATED);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[actix_web::test]
+    async fn concurrent_creates_all_reach_storage_when_writing_through() {
+        let dir = scratch_dir();
+        let mut config = config_in(&dir);
+        config.flush_interval = None;
+        let state = web::Data::new(load_state(config).await.unwrap());
+
+        create_concurrently(&state, 200).await;
+
+        assert_all_created(&stored(&dir), 200);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[actix_web::test]
+    async fn concurrent_creates_survive_a_crash_before_the_debounced_flush() {
+        let dir = scratch_dir();
+        let config = config_in(&dir);
+        assert!(config.flush_interval.is_some());
+        let state = web::Data::new(load_state(config).await.unwrap());
+
+        create_concurrently(&state, 200).await;
+        // no flusher runs here, so everything is still only in the write-ahead log
+        drop(state);
+        let restarted = load_state(config_in(&dir)).await.unwrap();
+        assert_all_created(&restarted.transactions.read().await, 200);
+        assert_all_created(&stored(&dir), 200);
+
+        restarted.flush().await.unwrap();
+        assert_all_created(&stored(&dir), 200);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
 }
@@ -0,0 +1,58 @@
+use prometheus::{Encoder, Histogram, HistogramOpts, IntCounter, IntGauge, Registry, TextEncoder};
+
+/// Prometheus collectors for the service, all registered on a private registry
+/// so `/metrics` only reports what we own.
+pub struct Metrics {
+    registry: Registry,
+    pub created: IntCounter,
+    pub updated: IntCounter,
+    pub deleted: IntCounter,
+    pub transactions: IntGauge,
+    pub persist_duration: Histogram,
+}
+
+impl Metrics {
+    pub fn new() -> prometheus::Result<Self> {
+        let registry = Registry::new();
+        let created = IntCounter::new(
+            "bookkeeping_transactions_created_total",
+            "Transactions created, including batch and CSV imports",
+        )?;
+        let updated = IntCounter::new(
+            "bookkeeping_transactions_updated_total",
+            "Transactions modified via PUT or PATCH",
+        )?;
+        let deleted = IntCounter::new(
+            "bookkeeping_transactions_deleted_total",
+            "Transactions soft-deleted",
+        )?;
+        let transactions = IntGauge::new(
+            "bookkeeping_transactions",
+            "Transactions currently stored, excluding deleted ones",
+        )?;
+        let persist_duration = Histogram::with_opts(HistogramOpts::new(
+            "bookkeeping_persist_duration_seconds",
+            "Time spent writing the ledger to storage",
+        ))?;
+        registry.register(Box::new(created.clone()))?;
+        registry.register(Box::new(updated.clone()))?;
+        registry.register(Box::new(deleted.clone()))?;
+        registry.register(Box::new(transactions.clone()))?;
+        registry.register(Box::new(persist_duration.clone()))?;
+        Ok(Self {
+            registry,
+            created,
+            updated,
+            deleted,
+            transactions,
+            persist_duration,
+        })
+    }
+
+    /// Render every registered collector in the Prometheus text format.
+    pub fn render(&self) -> prometheus::Result<String> {
+        let mut buf = Vec::new();
+        TextEncoder::new().encode(&self.registry.gather(), &mut buf)?;
+        Ok(String::from_utf8_lossy(&buf).into_owned())
+    }
+}
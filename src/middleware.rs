@@ -0,0 +1,268 @@
+use crate::envelope::{self, ApiResponse};
+use actix_cors::Cors;
+use actix_web::body::{self, EitherBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::{self, HeaderName, HeaderValue};
+use actix_web::middleware::Next;
+use actix_web::{Error, HttpResponse, web};
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tracing::Instrument;
+use uuid::Uuid;
+
+pub const API_KEY_HEADER: &str = "X-API-Key";
+pub const REQUEST_ID_HEADER: HeaderName = HeaderName::from_static("x-request-id");
+/// longer incoming ids are replaced rather than echoed
+const MAX_REQUEST_ID_LEN: usize = 128;
+
+/// Expected value of the `X-API-Key` header. Only registered as app data when
+/// `BOOKKEEPING_API_KEY` is set; without it the API runs open.
+#[derive(Clone)]
+pub struct ApiKey(pub String);
+
+/// Compare without short-circuiting so response timing doesn't leak the key.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// Reject requests whose `X-API-Key` header is missing or wrong with a 401.
+pub async fn require_api_key(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, Error> {
+    let Some(expected) = req.app_data::<web::Data<ApiKey>>() else {
+        return next
+            .call(req)
+            .await
+            .map(ServiceResponse::map_into_left_body);
+    };
+
+    let provided = req
+        .headers()
+        .get(API_KEY_HEADER)
+        .map(|v| v.as_bytes())
+        .unwrap_or_default();
+    if constant_time_eq(provided, expected.0.as_bytes()) {
+        return next
+            .call(req)
+            .await
+            .map(ServiceResponse::map_into_left_body);
+    }
+
+    let response = HttpResponse::Unauthorized().json(serde_json::json!({
+        "error": "missing or invalid API key",
+        "header": API_KEY_HEADER
+    }));
+    Ok(req.into_response(response).map_into_right_body())
+}
+
+/// Registered as app data when `BOOKKEEPING_READONLY` is set.
+#[derive(Clone)]
+pub struct ReadOnly;
+
+/// In read-only mode, answer anything but GET, HEAD and OPTIONS with a 403.
+pub async fn reject_writes(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, Error> {
+    if req.app_data::<web::Data<ReadOnly>>().is_none() || req.method().is_safe() {
+        return next
+            .call(req)
+            .await
+            .map(ServiceResponse::map_into_left_body);
+    }
+    let response =
+        HttpResponse::Forbidden().json(serde_json::json!({"error": "server is read-only"}));
+    Ok(req.into_response(response).map_into_right_body())
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+/// Token bucket per client IP: `capacity` requests of burst, refilled evenly
+/// over each minute.
+pub struct RateLimiter {
+    capacity: f64,
+    refill_per_sec: f64,
+    buckets: Mutex<HashMap<IpAddr, Bucket>>,
+}
+
+impl RateLimiter {
+    pub fn per_minute(requests: u32) -> Self {
+        let capacity = f64::from(requests);
+        Self {
+            capacity,
+            refill_per_sec: capacity / 60.0,
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Take a token for `ip`, or return how long until one is available.
+    fn acquire(&self, ip: IpAddr) -> Result<(), Duration> {
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap_or_else(|e| e.into_inner());
+        let bucket = buckets.entry(ip).or_insert(Bucket {
+            tokens: self.capacity,
+            updated: now,
+        });
+        let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * self.refill_per_sec).min(self.capacity);
+        bucket.updated = now;
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64(
+                (1.0 - bucket.tokens) / self.refill_per_sec,
+            ))
+        }
+    }
+
+    /// Drop buckets that have refilled completely; they're indistinguishable
+    /// from a fresh one, so keeping them only costs memory.
+    fn prune(&self) {
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap_or_else(|e| e.into_inner());
+        buckets.retain(|_, b| {
+            let refilled = now.duration_since(b.updated).as_secs_f64() * self.refill_per_sec;
+            b.tokens + refilled < self.capacity
+        });
+    }
+}
+
+/// Periodically prune idle buckets so one-off clients don't pile up.
+pub async fn run_bucket_cleanup(limiter: web::Data<RateLimiter>, every: Duration) {
+    let mut ticker = tokio::time::interval(every);
+    loop {
+        ticker.tick().await;
+        limiter.prune();
+    }
+}
+
+/// Answer with 429 and `Retry-After` once a client exhausts its bucket.
+pub async fn rate_limit(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, Error> {
+    let (Some(limiter), Some(peer)) = (req.app_data::<web::Data<RateLimiter>>(), req.peer_addr())
+    else {
+        return next
+            .call(req)
+            .await
+            .map(ServiceResponse::map_into_left_body);
+    };
+
+    match limiter.acquire(peer.ip()) {
+        Ok(()) => next
+            .call(req)
+            .await
+            .map(ServiceResponse::map_into_left_body),
+        Err(wait) => {
+            let retry_after = wait.as_secs_f64().ceil().max(1.0) as u64;
+            let response = HttpResponse::TooManyRequests()
+                .insert_header((header::RETRY_AFTER, retry_after))
+                .json(serde_json::json!({
+                    "error": "rate limit exceeded",
+                    "retry_after_secs": retry_after
+                }));
+            Ok(req.into_response(response).map_into_right_body())
+        }
+    }
+}
+
+/// Take the caller's `X-Request-Id` or mint one, run the request inside a
+/// tracing span carrying it, and echo it on the response.
+pub async fn request_id(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let id = req
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .map(str::trim)
+        .filter(|v| !v.is_empty() && v.len() <= MAX_REQUEST_ID_LEN)
+        .map(str::to_owned)
+        .unwrap_or_else(|| Uuid::new_v4().to_string());
+    let span = tracing::info_span!("request", request_id = %id);
+    let mut res = next.call(req).instrument(span).await?;
+    // either an id that already arrived as a header or a fresh uuid, so this always succeeds
+    if let Ok(value) = HeaderValue::from_str(&id) {
+        res.headers_mut().insert(REQUEST_ID_HEADER, value);
+    }
+    Ok(res)
+}
+
+/// Wrap successful JSON responses in an `ApiResponse` when the client opted in.
+/// Error bodies keep their usual shape, so clients still branch on the status.
+pub async fn envelope(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody, Vec<u8>>>, Error> {
+    let requested = envelope::requested(req.request());
+    let res = next.call(req).await?;
+    let is_json = res
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with("application/json"));
+    if !requested || !is_json || !res.status().is_success() {
+        return Ok(res.map_into_left_body());
+    }
+
+    let (req, res) = res.into_parts();
+    let (mut head, payload) = res.into_parts();
+    // streamed lists are buffered here; only opted-in clients pay for that
+    let payload = body::to_bytes(payload)
+        .await
+        .map_err(|e| actix_web::error::ErrorInternalServerError(e.into()))?;
+    let data: &serde_json::value::RawValue =
+        serde_json::from_slice(&payload).map_err(actix_web::error::ErrorInternalServerError)?;
+    let wrapped = serde_json::to_vec(&ApiResponse::new(data))
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    head.headers_mut().remove(header::CONTENT_LENGTH);
+    Ok(ServiceResponse::new(req, head.set_body(wrapped)).map_into_right_body())
+}
+
+/// Emit one structured log line per request with method, path, status and latency.
+pub async fn log_request(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let started = Instant::now();
+    let method = req.method().clone();
+    let path = req.path().to_owned();
+    let res = next.call(req).await;
+    let latency_ms = started.elapsed().as_secs_f64() * 1000.0;
+    match &res {
+        Ok(res) => {
+            let status = res.status().as_u16();
+            if res.status().is_server_error() {
+                tracing::warn!(%method, %path, status, latency_ms, "request");
+            } else {
+                tracing::info!(%method, %path, status, latency_ms, "request");
+            }
+        }
+        Err(e) => tracing::error!(%method, %path, error = %e, latency_ms, "request failed"),
+    }
+    res
+}
+
+/// Build the CORS layer. `None` allows any origin; otherwise only the listed
+/// origins may call the API from a browser (an empty list blocks them all).
+pub fn cors(allowed_origins: Option<&[String]>) -> Cors {
+    let Some(origins) = allowed_origins else {
+        return Cors::permissive();
+    };
+    origins
+        .iter()
+        .fold(Cors::default(), |cors, origin| cors.allowed_origin(origin))
+        .allowed_methods(["GET", "POST", "PUT", "PATCH", "DELETE", "OPTIONS"])
+        .allow_any_header()
+        .expose_any_header()
+        .max_age(3600)
+}
@@ -0,0 +1,123 @@
+use crate::audit::{AuditEntry, Diff, Modified};
+use crate::budget::{Budget, CreateBudget};
+use crate::envelope::ApiMeta;
+use crate::events::ChangeKind;
+use crate::recurring::{CreateRecurring, Interval, RecurringTransaction};
+use crate::transfer::{CreateTransfer, Transfer};
+use crate::verify::{Finding, VerifyReport};
+use crate::{
+    Bucket, BulkUpdate, CreateTransaction, EntryKind, FieldError, RestoreRequest, SortKey,
+    SortOrder, SplitPart, Stats, Transaction, UpdateTransaction,
+};
+use utoipa::OpenApi;
+use utoipa::openapi::{self, Server};
+
+/// Generated API description, served at /api-docs/openapi.json.
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "Bookkeeping API",
+        description = "Record, query and report on transactions. Send \
+            `Accept: application/vnd.bookkeeping.v1+json` or `?envelope=true` to get \
+            successful JSON responses as `{ data, meta: { api_version } }`. Any \
+            endpoint that reads or changes transactions answers 503 with a \
+            `Retry-After` header if the ledger stays busy past the lock timeout."
+    ),
+    paths(
+        crate::create_transaction,
+        crate::create_transactions_batch,
+        crate::import_transactions,
+        crate::list_transactions,
+        crate::count_transactions,
+        crate::latest_transactions,
+        crate::search_transactions,
+        crate::export_csv,
+        crate::export_json,
+        crate::get_transaction,
+        crate::get_transaction_by_seq,
+        crate::update_transaction,
+        crate::patch_transaction,
+        crate::delete_transaction,
+        crate::restore_transaction,
+        crate::clone_transaction,
+        crate::split_transaction,
+        crate::create_transfer,
+        crate::upload_receipt,
+        crate::get_receipt,
+        crate::transaction_history,
+        crate::bulk_delete_transactions,
+        crate::bulk_update_transactions,
+        crate::user_summary,
+        crate::merge_user,
+        crate::list_users,
+        crate::list_items,
+        crate::list_categories,
+        crate::user_statement,
+        crate::report_summary,
+        crate::report_timeseries,
+        crate::report_top_items,
+        crate::report_top_users,
+        crate::create_recurring,
+        crate::list_recurring,
+        crate::get_recurring,
+        crate::update_recurring,
+        crate::delete_recurring,
+        crate::create_budget,
+        crate::list_budgets,
+        crate::get_budget,
+        crate::update_budget,
+        crate::delete_budget,
+        crate::create_backup,
+        crate::restore_backup,
+        crate::verify_ledger,
+        crate::close_period,
+        crate::audit_diff,
+        crate::list_backups,
+        crate::list_archive,
+        crate::undo_change,
+        crate::redo_change,
+        crate::export_metrics,
+        crate::service_stats,
+        crate::live_updates,
+        crate::event_stream,
+    ),
+    components(schemas(
+        Transaction,
+        CreateTransaction,
+        UpdateTransaction,
+        BulkUpdate,
+        SplitPart,
+        CreateTransfer,
+        Transfer,
+        EntryKind,
+        FieldError,
+        SortKey,
+        SortOrder,
+        Bucket,
+        RecurringTransaction,
+        CreateRecurring,
+        Interval,
+        Budget,
+        AuditEntry,
+        Diff,
+        Modified,
+        ChangeKind,
+        CreateBudget,
+        RestoreRequest,
+        ApiMeta,
+        VerifyReport,
+        Finding,
+        Stats,
+    ))
+)]
+pub struct ApiDoc;
+
+/// The document for a server mounted at `base_path`, so "try it out" calls
+/// go to the right place.
+pub fn document(base_path: &str) -> openapi::OpenApi {
+    let mut doc = ApiDoc::openapi();
+    if !base_path.is_empty() {
+        doc.servers = Some(vec![Server::new(base_path)]);
+    }
+    doc
+}
@@ -0,0 +1,45 @@
+use crate::storage::read_json_file;
+use rust_decimal::Decimal;
+use std::collections::HashMap;
+use std::io;
+
+/// Exchange rates loaded once at startup. Each currency maps to the value of
+/// one unit in a common reference currency, e.g. `{"USD": 1.0, "EUR": 1.08}`,
+/// so any pair converts through the reference.
+#[derive(Debug, Default)]
+pub struct RateTable {
+    rates: HashMap<String, Decimal>,
+}
+
+impl RateTable {
+    /// Load the table from `path`. A missing file yields an empty table (only
+    /// same-currency amounts convert); an invalid one is an error.
+    pub async fn load(path: &str) -> io::Result<Self> {
+        let rates: HashMap<String, Decimal> = read_json_file(path).await?.unwrap_or_default();
+        if let Some((code, rate)) = rates.iter().find(|(_, r)| **r <= Decimal::ZERO) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "{}: rate for {} must be a positive number, got {}",
+                    path, code, rate
+                ),
+            ));
+        }
+        Ok(Self { rates })
+    }
+
+    pub fn len(&self) -> usize {
+        self.rates.len()
+    }
+
+    /// `amount` in `from` expressed in `to`, or None if either rate is unknown
+    /// or the result overflows.
+    pub fn convert(&self, amount: Decimal, from: &str, to: &str) -> Option<Decimal> {
+        if from == to {
+            return Some(amount);
+        }
+        amount
+            .checked_mul(*self.rates.get(from)?)?
+            .checked_div(*self.rates.get(to)?)
+    }
+}
@@ -0,0 +1,127 @@
+use std::io;
+use std::path::{Path, PathBuf};
+use tokio::fs;
+use uuid::Uuid;
+
+/// Accepted upload types and the extensions a stored file may carry for each.
+const ALLOWED_TYPES: &[(&str, &[&str])] = &[
+    ("image/jpeg", &["jpg", "jpeg"]),
+    ("image/png", &["png"]),
+    ("image/gif", &["gif"]),
+    ("image/webp", &["webp"]),
+    ("application/pdf", &["pdf"]),
+];
+/// longer names are cut, keeping the extension
+const MAX_FILENAME_CHARS: usize = 100;
+
+/// Receipt files, one directory per transaction under `dir`.
+pub struct Receipts {
+    dir: PathBuf,
+    /// uploads larger than this are refused with a 413
+    pub max_bytes: usize,
+}
+
+impl Receipts {
+    pub fn new(dir: impl Into<PathBuf>, max_bytes: usize) -> Self {
+        Self {
+            dir: dir.into(),
+            max_bytes,
+        }
+    }
+
+    /// Whether `content_type` may be uploaded at all.
+    pub fn accepts(content_type: &str) -> bool {
+        extensions(content_type).is_some()
+    }
+
+    /// Write `data` as the transaction's receipt, replacing any earlier one,
+    /// and return the stored path.
+    pub async fn save(
+        &self,
+        id: Uuid,
+        filename: &str,
+        content_type: &str,
+        data: &[u8],
+    ) -> io::Result<String> {
+        let folder = self.dir.join(id.to_string());
+        fs::create_dir_all(&folder).await?;
+        let path = folder.join(stored_name(filename, content_type));
+        let tmp_path = folder.join(".upload.tmp");
+        fs::write(&tmp_path, data).await?;
+        fs::rename(&tmp_path, &path).await?;
+
+        // only one receipt per transaction; older uploads go once the new one is in place
+        let mut entries = fs::read_dir(&folder).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            if entry.path() != path
+                && let Err(e) = fs::remove_file(entry.path()).await
+            {
+                tracing::warn!(error = %e, file = %entry.path().display(), "Failed to remove old receipt");
+            }
+        }
+        Ok(path.to_string_lossy().into_owned())
+    }
+
+    /// The stored bytes and their content type.
+    pub async fn read(&self, stored: &str) -> io::Result<(Vec<u8>, &'static str)> {
+        let path = Path::new(stored);
+        let content_type = content_type_of(path).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidData, "unrecognised receipt type")
+        })?;
+        Ok((fs::read(path).await?, content_type))
+    }
+}
+
+fn extensions(content_type: &str) -> Option<&'static [&'static str]> {
+    ALLOWED_TYPES
+        .iter()
+        .find(|(mime, _)| mime.eq_ignore_ascii_case(content_type))
+        .map(|(_, exts)| *exts)
+}
+
+fn content_type_of(path: &Path) -> Option<&'static str> {
+    let ext = path.extension()?.to_str()?.to_ascii_lowercase();
+    ALLOWED_TYPES
+        .iter()
+        .find(|(_, exts)| exts.contains(&ext.as_str()))
+        .map(|(mime, _)| *mime)
+}
+
+/// The client's file name reduced to a safe base name, with an extension
+/// that matches the declared type so it is served back as the same type.
+fn stored_name(filename: &str, content_type: &str) -> String {
+    let base = filename.rsplit(['/', '\\']).next().unwrap_or_default();
+    let mut name: String = base
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    name = name.trim_start_matches('.').to_string();
+    let exts = extensions(content_type).unwrap_or(&["bin"]);
+    let has_ext = Path::new(&name)
+        .extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| exts.contains(&e.to_ascii_lowercase().as_str()));
+    if !has_ext {
+        if name.is_empty() {
+            name.push_str("receipt");
+        }
+        name.push('.');
+        name.push_str(exts[0]);
+    }
+    if name.chars().count() > MAX_FILENAME_CHARS {
+        let ext = Path::new(&name)
+            .extension()
+            .and_then(|e| e.to_str())
+            .unwrap_or_default()
+            .to_string();
+        let keep = MAX_FILENAME_CHARS - ext.len() - 1;
+        name = format!("{}.{}", name.chars().take(keep).collect::<String>(), ext);
+    }
+    name
+}
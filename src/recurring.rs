@@ -0,0 +1,131 @@
+use crate::storage::{read_json_file, write_json_file};
+use crate::{CreateTransaction, FieldError, Transaction, now_secs};
+use chrono::{DateTime, Months, Utc};
+use serde::{Deserialize, Serialize};
+use std::io;
+use tokio::sync::RwLock;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+/// Upper bound on entries materialized per template in one pass, so a daily
+/// template with a start date years in the past can't stall the ledger.
+const MAX_CATCH_UP: u32 = 1000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Interval {
+    Daily,
+    Weekly,
+    Monthly,
+}
+
+impl Interval {
+    /// Timestamp of the `n`th occurrence counted from `start`. Months are added to
+    /// the start date rather than chained, so a template on the 31st doesn't drift
+    /// to the 28th after February.
+    fn nth(self, start: u64, n: u32) -> Option<u64> {
+        match self {
+            Interval::Daily => start.checked_add(u64::from(n) * 86_400),
+            Interval::Weekly => start.checked_add(u64::from(n) * 7 * 86_400),
+            Interval::Monthly => {
+                let dt: DateTime<Utc> = DateTime::from_timestamp(i64::try_from(start).ok()?, 0)?;
+                let next = dt.checked_add_months(Months::new(n))?;
+                u64::try_from(next.timestamp()).ok()
+            }
+        }
+    }
+}
+
+/// A template that turns into a real transaction every `interval`, starting at `start`.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct RecurringTransaction {
+    pub id: Uuid,
+    pub interval: Interval,
+    /// timestamp of the first occurrence
+    pub start: u64,
+    /// occurrences materialized so far
+    pub runs: u32,
+    /// timestamp the next transaction will carry; None once past the representable range
+    pub next_run: Option<u64>,
+    #[serde(flatten)]
+    pub template: CreateTransaction,
+}
+
+/// Body for POST and PUT /recurring. `timestamp` is the first occurrence and
+/// defaults to now.
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct CreateRecurring {
+    pub interval: Interval,
+    #[serde(flatten)]
+    pub template: CreateTransaction,
+}
+
+impl CreateRecurring {
+    pub fn validate(&self) -> Result<(), Vec<FieldError>> {
+        self.template.validate()
+    }
+
+    /// Build a fresh template; call validate() first.
+    pub fn into_recurring(self) -> RecurringTransaction {
+        let mut template = self.template;
+        let start = template.timestamp.take().unwrap_or_else(now_secs);
+        RecurringTransaction {
+            id: Uuid::new_v4(),
+            interval: self.interval,
+            start,
+            runs: 0,
+            next_run: Some(start),
+            template,
+        }
+    }
+}
+
+impl RecurringTransaction {
+    /// Produce every occurrence due at or before `now` and advance the schedule.
+    fn take_due(&mut self, now: u64) -> Vec<Transaction> {
+        let mut due = Vec::new();
+        while let Some(ts) = self.next_run.filter(|&ts| ts <= now) {
+            if due.len() as u32 >= MAX_CATCH_UP {
+                break;
+            }
+            let mut tx = self.template.to_transaction();
+            tx.timestamp = ts;
+            if !tx.tags.iter().any(|t| t == "recurring") {
+                tx.tags.push("recurring".to_string());
+            }
+            due.push(tx);
+            self.runs += 1;
+            self.next_run = self.interval.nth(self.start, self.runs);
+        }
+        due
+    }
+}
+
+/// Recurring templates, kept in their own JSON file next to the ledger.
+pub struct RecurringStore {
+    file_path: String,
+    pub templates: RwLock<Vec<RecurringTransaction>>,
+}
+
+impl RecurringStore {
+    pub async fn load(file_path: impl Into<String>) -> io::Result<Self> {
+        let file_path = file_path.into();
+        let templates = read_json_file(&file_path).await?.unwrap_or_default();
+        Ok(Self {
+            file_path,
+            templates: RwLock::new(templates),
+        })
+    }
+
+    pub async fn save(&self, templates: &[RecurringTransaction]) -> io::Result<()> {
+        write_json_file(&self.file_path, templates).await
+    }
+
+    /// Advance every template up to `now`, returning the transactions to append.
+    pub fn materialize(templates: &mut [RecurringTransaction], now: u64) -> Vec<Transaction> {
+        templates
+            .iter_mut()
+            .flat_map(|template| template.take_due(now))
+            .collect()
+    }
+}
@@ -0,0 +1,163 @@
+use crate::Transaction;
+use crate::format::{self, Locale};
+use chrono::{DateTime, Months, NaiveDate, Utc};
+use rust_decimal::Decimal;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+/// One calendar month in UTC, parsed from `YYYY-MM`.
+pub struct StatementPeriod {
+    pub first_day: NaiveDate,
+    pub last_day: NaiveDate,
+    /// inclusive UNIX-second bounds covering the whole month
+    pub from: u64,
+    pub to: u64,
+}
+
+impl StatementPeriod {
+    pub fn parse(month: &str) -> Option<Self> {
+        let first_day =
+            NaiveDate::parse_from_str(&format!("{}-01", month.trim()), "%Y-%m-%d").ok()?;
+        let next = first_day.checked_add_months(Months::new(1))?;
+        let from = u64::try_from(first_day.and_hms_opt(0, 0, 0)?.and_utc().timestamp()).ok()?;
+        let next_from = u64::try_from(next.and_hms_opt(0, 0, 0)?.and_utc().timestamp()).ok()?;
+        Some(Self {
+            first_day,
+            last_day: next.pred_opt()?,
+            from,
+            to: next_from - 1,
+        })
+    }
+
+    /// The month `timestamp` falls in.
+    pub fn containing(timestamp: u64) -> Option<Self> {
+        let dt = DateTime::<Utc>::from_timestamp(i64::try_from(timestamp).ok()?, 0)?;
+        Self::parse(&dt.format("%Y-%m").to_string())
+    }
+
+    pub fn contains(&self, timestamp: u64) -> bool {
+        (self.from..=self.to).contains(&timestamp)
+    }
+}
+
+#[derive(Serialize)]
+pub struct Statement<'a> {
+    pub user: &'a str,
+    pub opening_date: String,
+    pub closing_date: String,
+    pub transaction_count: usize,
+    /// keyed by currency, like every other money total
+    pub total: BTreeMap<&'a str, Decimal>,
+    /// each total spelled out, for printing on formal statements
+    pub total_in_words: BTreeMap<&'a str, String>,
+    pub transactions: Vec<&'a Transaction>,
+}
+
+impl<'a> Statement<'a> {
+    /// `txs` should already be limited to the user, the period and live rows.
+    pub fn new(user: &'a str, period: &StatementPeriod, mut txs: Vec<&'a Transaction>) -> Self {
+        txs.sort_by_key(|t| (t.timestamp, t.id));
+        let mut total: BTreeMap<&str, Decimal> = BTreeMap::new();
+        for t in &txs {
+            *total.entry(&t.currency).or_default() += t.amount;
+        }
+        crate::round_totals(&mut total);
+        let total_in_words = total
+            .iter()
+            .map(|(currency, amount)| (*currency, format::amount_in_words(*amount, currency)))
+            .collect();
+        Self {
+            user,
+            opening_date: period.first_day.to_string(),
+            closing_date: period.last_day.to_string(),
+            transaction_count: txs.len(),
+            total,
+            total_in_words,
+            transactions: txs,
+        }
+    }
+
+    /// A self-contained printable page with amounts written for `locale`;
+    /// every user-supplied string is escaped.
+    pub fn to_html(&self, locale: Locale) -> String {
+        let mut html = String::new();
+        let title = format!(
+            "Statement for {} ({} to {})",
+            self.user, self.opening_date, self.closing_date
+        );
+        let _ = write!(
+            html,
+            "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n\
+             <style>\nbody {{ font-family: sans-serif; margin: 2em; }}\n\
+             table {{ border-collapse: collapse; width: 100%; }}\n\
+             th, td {{ border-bottom: 1px solid #ccc; padding: 0.3em 0.6em; text-align: left; }}\n\
+             td.amount, th.amount {{ text-align: right; }}\n\
+             tfoot td {{ font-weight: bold; border-top: 2px solid #000; }}\n\
+             tfoot td.words {{ font-weight: normal; font-style: italic; border-top: none; }}\n\
+             </style>\n</head>\n<body>\n<h1>{}</h1>\n\
+             <p>Period: {} to {}<br>Transactions: {}</p>\n",
+            escape(&title),
+            escape(&title),
+            self.opening_date,
+            self.closing_date,
+            self.transaction_count
+        );
+        html.push_str(
+            "<table>\n<thead><tr><th>Date</th><th>Item</th><th>Category</th>\
+             <th class=\"amount\">Amount</th><th>Currency</th></tr></thead>\n<tbody>\n",
+        );
+        for t in &self.transactions {
+            let date = i64::try_from(t.timestamp)
+                .ok()
+                .and_then(|ts| DateTime::<Utc>::from_timestamp(ts, 0))
+                .map(|dt| dt.format("%Y-%m-%d").to_string())
+                .unwrap_or_default();
+            let _ = writeln!(
+                html,
+                "<tr><td>{}</td><td>{}</td><td>{}</td><td class=\"amount\">{}</td><td>{}</td></tr>",
+                date,
+                escape(&t.item),
+                escape(t.category.as_deref().unwrap_or("")),
+                escape(&locale.format_money(t.amount, &t.currency)),
+                escape(&t.currency)
+            );
+        }
+        html.push_str("</tbody>\n<tfoot>\n");
+        if self.total.is_empty() {
+            html.push_str("<tr><td colspan=\"5\">No transactions in this period</td></tr>\n");
+        }
+        for (currency, amount) in &self.total {
+            let _ = writeln!(
+                html,
+                "<tr><td colspan=\"3\">Total</td><td class=\"amount\">{}</td><td>{}</td></tr>",
+                escape(&locale.format_money(*amount, currency)),
+                escape(currency)
+            );
+            if let Some(words) = self.total_in_words.get(currency) {
+                let _ = writeln!(
+                    html,
+                    "<tr><td colspan=\"5\" class=\"words\">{}</td></tr>",
+                    escape(words)
+                );
+            }
+        }
+        html.push_str("</tfoot>\n</table>\n</body>\n</html>\n");
+        html
+    }
+}
+
+fn escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
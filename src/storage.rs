@@ -0,0 +1,352 @@
+use crate::Transaction;
+use async_trait::async_trait;
+use rusqlite::{Connection, params};
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::fs;
+use uuid::Uuid;
+
+/// Backend that the in-memory transaction list is loaded from and saved to.
+#[async_trait]
+pub trait Storage {
+    async fn load(&self) -> io::Result<Vec<Transaction>>;
+    async fn save(&self, txs: &[Transaction]) -> io::Result<()>;
+
+    /// Remove any leftovers from interrupted saves; called once on shutdown.
+    async fn cleanup(&self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Tries save_with_retry makes before giving up.
+const SAVE_ATTEMPTS: u32 = 3;
+/// wait before the first retry, doubled before each one after
+const SAVE_RETRY_DELAY: Duration = Duration::from_millis(50);
+
+/// `storage.save(txs)`, retried with exponential backoff when the error could
+/// be a passing filesystem hiccup. Every failed attempt is logged.
+pub async fn save_with_retry(
+    storage: &(dyn Storage + Send + Sync),
+    txs: &[Transaction],
+) -> io::Result<()> {
+    let mut delay = SAVE_RETRY_DELAY;
+    let mut attempt = 1;
+    loop {
+        let Err(e) = storage.save(txs).await else {
+            if attempt > 1 {
+                tracing::info!(attempt, "Storage save succeeded after retrying");
+            }
+            return Ok(());
+        };
+        // bad data fails the same way every time
+        let transient = !matches!(
+            e.kind(),
+            io::ErrorKind::InvalidData | io::ErrorKind::InvalidInput
+        );
+        if !transient || attempt == SAVE_ATTEMPTS {
+            tracing::warn!(error = %e, attempt, "Storage save failed; giving up");
+            return Err(e);
+        }
+        tracing::warn!(
+            error = %e,
+            attempt,
+            retry_in_ms = delay.as_millis() as u64,
+            "Storage save failed; retrying"
+        );
+        tokio::time::sleep(delay).await;
+        delay *= 2;
+        attempt += 1;
+    }
+}
+
+/// Drop all but the last copy of any id that appears more than once, keeping
+/// the survivors in order. Returns the ids that were repeated.
+pub fn dedupe_ids(txs: Vec<Transaction>) -> (Vec<Transaction>, Vec<Uuid>) {
+    let mut seen = HashSet::new();
+    let mut repeated = HashSet::new();
+    let mut kept: Vec<Transaction> = txs
+        .into_iter()
+        .rev()
+        .filter(|tx| {
+            let first = seen.insert(tx.id);
+            if !first {
+                repeated.insert(tx.id);
+            }
+            first
+        })
+        .collect();
+    kept.reverse();
+    (kept, repeated.into_iter().collect())
+}
+
+/// Read a JSON document, or `None` if the file doesn't exist yet.
+pub async fn read_json_file<T: DeserializeOwned>(path: &str) -> io::Result<Option<T>> {
+    match fs::read(path).await {
+        Ok(data) => Ok(Some(serde_json::from_slice(&data)?)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Pretty-print `value` to a temp file next to `path`, then rename it into place.
+pub async fn write_json_file<T: Serialize + ?Sized>(path: &str, value: &T) -> io::Result<()> {
+    write_json(path, value, true).await
+}
+
+/// Like write_json_file, but compact unless `pretty` is set.
+async fn write_json<T: Serialize + ?Sized>(path: &str, value: &T, pretty: bool) -> io::Result<()> {
+    let data = if pretty {
+        serde_json::to_vec_pretty(value)?
+    } else {
+        serde_json::to_vec(value)?
+    };
+    let tmp_path = format!("{}.tmp", path);
+    fs::write(&tmp_path, data).await?;
+    fs::rename(&tmp_path, path).await
+}
+
+/// Stores the whole list as a JSON array in a single file.
+pub struct JsonFileStorage {
+    file_path: String,
+    /// move an unparseable file aside and start empty instead of failing to load
+    quarantine_corrupt: bool,
+    /// indent the file for reading by hand; compact output is smaller and faster
+    pretty: bool,
+}
+
+impl JsonFileStorage {
+    pub fn new(file_path: impl Into<String>) -> Self {
+        Self {
+            file_path: file_path.into(),
+            quarantine_corrupt: false,
+            pretty: true,
+        }
+    }
+
+    pub fn pretty(mut self, enabled: bool) -> Self {
+        self.pretty = enabled;
+        self
+    }
+
+    pub fn quarantine_corrupt(mut self, enabled: bool) -> Self {
+        self.quarantine_corrupt = enabled;
+        self
+    }
+
+    fn tmp_path(&self) -> String {
+        format!("{}.tmp", &self.file_path)
+    }
+}
+
+#[async_trait]
+impl Storage for JsonFileStorage {
+    async fn load(&self) -> io::Result<Vec<Transaction>> {
+        if !Path::new(&self.file_path).exists() {
+            return Ok(Vec::new());
+        }
+        let data = fs::read(&self.file_path).await?;
+        match serde_json::from_slice(&data) {
+            Ok(txs) => Ok(txs),
+            Err(e) if self.quarantine_corrupt => {
+                // keep the bytes around for manual recovery; the next save starts a fresh file
+                let aside = format!("{}.corrupt.{}", self.file_path, crate::now_secs());
+                fs::rename(&self.file_path, &aside).await?;
+                tracing::error!(
+                    error = %e,
+                    file = %self.file_path,
+                    moved_to = %aside,
+                    "Storage file is corrupt; moved it aside and starting with no transactions"
+                );
+                Ok(Vec::new())
+            }
+            // never fall back to an empty ledger here: the next persist would overwrite the file
+            Err(e) => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{} is not a valid transaction file: {}", self.file_path, e),
+            )),
+        }
+    }
+
+    async fn save(&self, txs: &[Transaction]) -> io::Result<()> {
+        // Write to temp file then rename for atomicity
+        write_json(&self.file_path, txs, self.pretty).await
+    }
+
+    async fn cleanup(&self) -> io::Result<()> {
+        match fs::remove_file(self.tmp_path()).await {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Stores one row per transaction and only writes rows that changed since the last save.
+pub struct SqliteStorage {
+    conn: Arc<Mutex<Connection>>,
+    /// What the database currently holds, so save() can diff instead of rewriting everything.
+    saved: Arc<Mutex<HashMap<Uuid, Transaction>>>,
+}
+
+impl SqliteStorage {
+    /// Open (or create) the database and run the schema migration.
+    pub fn open(db_path: impl AsRef<Path>) -> io::Result<Self> {
+        let conn = Connection::open(db_path).map_err(io::Error::other)?;
+        // The full record lives in `data` so new fields never need a column migration;
+        // `user` and `timestamp` are broken out for ad-hoc queries against the db.
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS transactions (
+                id TEXT PRIMARY KEY NOT NULL,
+                user TEXT NOT NULL,
+                timestamp INTEGER NOT NULL,
+                data TEXT NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS idx_transactions_user ON transactions(user);",
+        )
+        .map_err(io::Error::other)?;
+        Ok(Self {
+            conn: Arc::new(Mutex::new(conn)),
+            saved: Arc::new(Mutex::new(HashMap::new())),
+        })
+    }
+}
+
+#[async_trait]
+impl Storage for SqliteStorage {
+    async fn load(&self) -> io::Result<Vec<Transaction>> {
+        let conn = self.conn.clone();
+        let txs = tokio::task::spawn_blocking(move || -> io::Result<Vec<Transaction>> {
+            let conn = conn.lock().unwrap_or_else(|e| e.into_inner());
+            // rowid follows insertion order, which is the order the list is served in
+            let mut stmt = conn
+                .prepare("SELECT data FROM transactions ORDER BY rowid")
+                .map_err(io::Error::other)?;
+            let rows = stmt
+                .query_map([], |row| row.get::<_, String>(0))
+                .map_err(io::Error::other)?;
+            let mut txs = Vec::new();
+            for data in rows {
+                let data = data.map_err(io::Error::other)?;
+                txs.push(serde_json::from_str(&data)?);
+            }
+            Ok(txs)
+        })
+        .await
+        .map_err(io::Error::other)??;
+
+        let mut saved = self.saved.lock().unwrap_or_else(|e| e.into_inner());
+        *saved = txs.iter().map(|t| (t.id, t.clone())).collect();
+        Ok(txs)
+    }
+
+    async fn save(&self, txs: &[Transaction]) -> io::Result<()> {
+        let current: HashMap<Uuid, Transaction> = txs.iter().map(|t| (t.id, t.clone())).collect();
+        let (upserts, deletes) = {
+            let saved = self.saved.lock().unwrap_or_else(|e| e.into_inner());
+            let upserts: Vec<Transaction> = txs
+                .iter()
+                .filter(|t| saved.get(&t.id) != Some(*t))
+                .cloned()
+                .collect();
+            let deletes: Vec<Uuid> = saved
+                .keys()
+                .filter(|id| !current.contains_key(id))
+                .copied()
+                .collect();
+            (upserts, deletes)
+        };
+        if upserts.is_empty() && deletes.is_empty() {
+            return Ok(());
+        }
+
+        let conn = self.conn.clone();
+        tokio::task::spawn_blocking(move || -> io::Result<()> {
+            let mut conn = conn.lock().unwrap_or_else(|e| e.into_inner());
+            let db_tx = conn.transaction().map_err(io::Error::other)?;
+            {
+                let mut upsert = db_tx
+                    .prepare(
+                        "INSERT INTO transactions (id, user, timestamp, data) VALUES (?1, ?2, ?3, ?4)
+                         ON CONFLICT(id) DO UPDATE SET
+                            user = excluded.user,
+                            timestamp = excluded.timestamp,
+                            data = excluded.data",
+                    )
+                    .map_err(io::Error::other)?;
+                for t in &upserts {
+                    let data = serde_json::to_string(t)?;
+                    upsert
+                        .execute(params![t.id.to_string(), t.user, t.timestamp as i64, data])
+                        .map_err(io::Error::other)?;
+                }
+                let mut delete = db_tx
+                    .prepare("DELETE FROM transactions WHERE id = ?1")
+                    .map_err(io::Error::other)?;
+                for id in &deletes {
+                    delete
+                        .execute(params![id.to_string()])
+                        .map_err(io::Error::other)?;
+                }
+            }
+            db_tx.commit().map_err(io::Error::other)
+        })
+        .await
+        .map_err(io::Error::other)??;
+
+        // only remember the new state once it is committed
+        let mut saved = self.saved.lock().unwrap_or_else(|e| e.into_inner());
+        *saved = current;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A file with `a` stored twice around `b`, as a bad hand edit might leave it.
+    fn crafted_file(a: Uuid, b: Uuid) -> String {
+        let row = |id: Uuid, item: &str| serde_json::json!({"id": id, "user": "u", "item": item, "amount": 1, "timestamp": 1});
+        serde_json::json!([row(a, "first a"), row(b, "b"), row(a, "last a")]).to_string()
+    }
+
+    #[tokio::test]
+    async fn duplicate_ids_keep_the_last_copy_in_order() {
+        let (a, b) = (Uuid::new_v4(), Uuid::new_v4());
+        let path = std::env::temp_dir().join(format!("myday-dupes-{}.json", Uuid::new_v4()));
+        std::fs::write(&path, crafted_file(a, b)).unwrap();
+
+        let loaded = JsonFileStorage::new(path.to_string_lossy())
+            .load()
+            .await
+            .unwrap();
+        let (kept, repeated) = dedupe_ids(loaded);
+
+        let items: Vec<&str> = kept.iter().map(|t| t.item.as_str()).collect();
+        assert_eq!(items, ["b", "last a"]);
+        assert_eq!(repeated, [a]);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn unique_ids_are_left_alone() {
+        let txs: Vec<Transaction> = serde_json::from_str(&format!(
+            "[{}]",
+            ["x", "y", "z"]
+                .map(|item| format!(
+                    r#"{{"id":"{}","user":"u","item":"{}","amount":1,"timestamp":1}}"#,
+                    Uuid::new_v4(),
+                    item
+                ))
+                .join(",")
+        ))
+        .unwrap();
+        let (kept, repeated) = dedupe_ids(txs.clone());
+        assert_eq!(kept, txs);
+        assert!(repeated.is_empty());
+    }
+}
@@ -0,0 +1,151 @@
+use actix_web::web::Bytes;
+use futures_util::Stream;
+use serde::Serialize;
+use std::io;
+
+/// Array elements serialized per body chunk.
+pub const ITEMS_PER_CHUNK: usize = 64;
+
+/// A response body of `prefix`, then `items` as a JSON array, then `suffix`.
+/// Elements are serialized a chunk at a time as the client reads, so the whole
+/// document never sits in memory at once.
+pub fn json_array_body<T: Serialize>(
+    prefix: String,
+    items: Vec<T>,
+    suffix: &'static str,
+) -> impl Stream<Item = Result<Bytes, serde_json::Error>> {
+    let mut prefix = Some(prefix);
+    let mut items = items.into_iter().peekable();
+    let mut first = true;
+    let mut done = false;
+    futures_util::stream::iter(std::iter::from_fn(move || {
+        if done {
+            return None;
+        }
+        let mut buf = Vec::new();
+        if let Some(prefix) = prefix.take() {
+            buf.extend_from_slice(prefix.as_bytes());
+            buf.push(b'[');
+        }
+        for item in items.by_ref().take(ITEMS_PER_CHUNK) {
+            if !first {
+                buf.push(b',');
+            }
+            first = false;
+            if let Err(e) = serde_json::to_writer(&mut buf, &item) {
+                done = true;
+                return Some(Err(e));
+            }
+        }
+        if items.peek().is_none() {
+            buf.push(b']');
+            buf.extend_from_slice(suffix.as_bytes());
+            done = true;
+        }
+        Some(Ok(Bytes::from(buf)))
+    }))
+}
+
+/// Like `json_array_body`, for lists too big to copy out whole: `fetch(position)`
+/// is called once per chunk and returns up to `ITEMS_PER_CHUNK` elements plus
+/// the position to continue from, or no elements at the end. Each call can
+/// take and release its own lock, so readers hold it only per chunk.
+pub fn json_array_pages<T, F, Fut>(
+    prefix: String,
+    fetch: F,
+    suffix: &'static str,
+) -> impl Stream<Item = Result<Bytes, io::Error>>
+where
+    T: Serialize,
+    F: FnMut(usize) -> Fut,
+    Fut: Future<Output = io::Result<(Vec<T>, usize)>>,
+{
+    struct Cursor<F> {
+        fetch: F,
+        position: usize,
+        /// taken with the first chunk
+        prefix: Option<String>,
+        done: bool,
+    }
+    let start = Cursor {
+        fetch,
+        position: 0,
+        prefix: Some(prefix),
+        done: false,
+    };
+    futures_util::stream::unfold(start, move |mut cursor| async move {
+        if cursor.done {
+            return None;
+        }
+        let mut buf = Vec::new();
+        let first = match cursor.prefix.take() {
+            Some(prefix) => {
+                buf.extend_from_slice(prefix.as_bytes());
+                buf.push(b'[');
+                true
+            }
+            None => false,
+        };
+        let items = match (cursor.fetch)(cursor.position).await {
+            Ok((items, next)) => {
+                cursor.position = next;
+                items
+            }
+            Err(e) => {
+                cursor.done = true;
+                return Some((Err(e), cursor));
+            }
+        };
+        for (i, item) in items.iter().enumerate() {
+            if i > 0 || !first {
+                buf.push(b',');
+            }
+            if let Err(e) = serde_json::to_writer(&mut buf, item) {
+                cursor.done = true;
+                return Some((Err(e.into()), cursor));
+            }
+        }
+        if items.is_empty() {
+            buf.push(b']');
+            buf.extend_from_slice(suffix.as_bytes());
+            cursor.done = true;
+        }
+        Some((Ok(Bytes::from(buf)), cursor))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures_util::StreamExt;
+
+    async fn body<S: Stream<Item = Result<Bytes, E>>, E: std::fmt::Debug>(stream: S) -> String {
+        let mut stream = std::pin::pin!(stream);
+        let mut out = Vec::new();
+        while let Some(chunk) = stream.next().await {
+            out.extend_from_slice(&chunk.unwrap());
+        }
+        String::from_utf8(out).unwrap()
+    }
+
+    #[tokio::test]
+    async fn pages_join_into_one_array() {
+        let rows: Vec<usize> = (0..150).collect();
+        let fetch = |start: usize| {
+            let rows = rows.clone();
+            async move {
+                let stop = (start + ITEMS_PER_CHUNK).min(rows.len());
+                Ok((rows[start..stop].to_vec(), stop))
+            }
+        };
+        let text = body(json_array_pages("{\"items\":".into(), fetch, "}")).await;
+        let value: serde_json::Value = serde_json::from_str(&text).unwrap();
+        assert_eq!(value["items"], serde_json::json!(rows));
+    }
+
+    #[tokio::test]
+    async fn an_empty_list_is_an_empty_array() {
+        let fetch = |_| async { Ok((Vec::<u8>::new(), 0)) };
+        assert_eq!(body(json_array_pages("x".into(), fetch, "y")).await, "x[]y");
+    }
+}
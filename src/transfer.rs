@@ -0,0 +1,128 @@
+use crate::{
+    CURRENCY_ERROR, CreateTransaction, EntryKind, FieldError, Transaction, default_currency,
+    deserialize_amount, deserialize_timestamp, is_valid_currency, normalize_note,
+};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+/// Body for POST /transfers: `amount` leaves `from` and arrives at `to`.
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct CreateTransfer {
+    pub from: String,
+    pub to: String,
+    /// positive magnitude; a number or a numeric string
+    #[serde(deserialize_with = "deserialize_amount")]
+    pub amount: Decimal,
+    #[serde(default = "default_currency")]
+    pub currency: String,
+    /// defaults to "Transfer to <to>" and "Transfer from <from>"
+    #[serde(default)]
+    pub item: Option<String>,
+    #[serde(default)]
+    pub category: Option<String>,
+    /// UNIX seconds or an ISO 8601 string; defaults to now
+    #[serde(default, deserialize_with = "deserialize_timestamp")]
+    pub timestamp: Option<u64>,
+    #[serde(default)]
+    pub note: Option<String>,
+}
+
+/// The two legs of a transfer, as returned by POST /transfers.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct Transfer {
+    pub transfer_id: Uuid,
+    /// the sender's leg, a debit
+    pub debit: Transaction,
+    /// the receiver's leg, a credit
+    pub credit: Transaction,
+}
+
+impl CreateTransfer {
+    /// The sender's and receiver's entries, as ordinary creates.
+    fn legs(&self) -> [CreateTransaction; 2] {
+        let leg = |user: &str, kind, item: String| CreateTransaction {
+            user: user.to_string(),
+            item: self.item.clone().unwrap_or(item),
+            amount: self.amount,
+            kind: Some(kind),
+            timestamp: self.timestamp,
+            category: self.category.clone(),
+            currency: self.currency.clone(),
+            note: self.note.clone(),
+            tags: vec!["transfer".to_string()],
+            attachment_url: None,
+        };
+        [
+            leg(
+                &self.from,
+                EntryKind::Debit,
+                format!("Transfer to {}", self.to.trim()),
+            ),
+            leg(
+                &self.to,
+                EntryKind::Credit,
+                format!("Transfer from {}", self.from.trim()),
+            ),
+        ]
+    }
+
+    pub fn validate(&self) -> Result<(), Vec<FieldError>> {
+        let mut errors = Vec::new();
+        if self.from.trim().is_empty() {
+            errors.push(FieldError::new("from", "required"));
+        }
+        if self.to.trim().is_empty() {
+            errors.push(FieldError::new("to", "required"));
+        }
+        if !self.from.trim().is_empty() && self.from.trim() == self.to.trim() {
+            errors.push(FieldError::new("to", "must differ from `from`"));
+        }
+        if self.amount <= Decimal::ZERO {
+            errors.push(FieldError::new("amount", "must be a positive number"));
+        }
+        if !is_valid_currency(&self.currency) {
+            errors.push(FieldError::new("currency", CURRENCY_ERROR));
+        }
+        if self.item.as_ref().is_some_and(|i| i.trim().is_empty()) {
+            errors.push(FieldError::new(
+                "item",
+                "must be a non-empty string when provided",
+            ));
+        }
+        if self.category.as_ref().is_some_and(|c| c.trim().is_empty()) {
+            errors.push(FieldError::new(
+                "category",
+                "must be a non-empty string when provided",
+            ));
+        }
+        if let Some(Err(msg)) = self.note.as_deref().map(normalize_note) {
+            errors.push(FieldError::new("note", msg));
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Build both legs under a fresh transfer id; call validate() first.
+    pub fn into_transfer(self) -> Transfer {
+        let transfer_id = Uuid::new_v4();
+        let [debit, credit] = self.legs().map(|leg| Transaction {
+            transfer_id: Some(transfer_id),
+            ..leg.to_transaction()
+        });
+        // one moment for both, even when the timestamp defaulted to now
+        let credit = Transaction {
+            timestamp: debit.timestamp,
+            ..credit
+        };
+        Transfer {
+            transfer_id,
+            debit,
+            credit,
+        }
+    }
+}
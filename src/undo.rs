@@ -0,0 +1,160 @@
+use crate::Transaction;
+use crate::events::ChangeKind;
+use crate::ledger::Ledger;
+use std::collections::{HashSet, VecDeque};
+use std::sync::Mutex;
+use uuid::Uuid;
+
+/// How to reverse one mutation.
+#[derive(Debug)]
+pub enum Inverse {
+    /// the mutation added these ids; reversing drops them again
+    Remove(Vec<Uuid>),
+    /// the mutation dropped these; reversing puts them back
+    Insert(Vec<Transaction>),
+    /// the mutation changed these; reversing puts the saved states back
+    Revert(Vec<Transaction>),
+    /// a mutation made of several of the above, reversed in the given order
+    All(Vec<Inverse>),
+}
+
+/// One reversible step, named after the request that made it.
+#[derive(Debug)]
+pub struct Step {
+    pub action: &'static str,
+    pub inverse: Inverse,
+}
+
+/// What applying a step changed, one entry per affected transaction.
+pub struct Applied {
+    pub action: &'static str,
+    pub changes: Vec<(ChangeKind, Transaction)>,
+}
+
+impl Step {
+    pub fn new(action: &'static str, inverse: Inverse) -> Self {
+        Self { action, inverse }
+    }
+
+    /// Apply to the ledger, returning the change and the step that reverses it.
+    fn apply(self, ledger: &mut Ledger) -> (Applied, Step) {
+        let mut changes = Vec::new();
+        let inverse = self.inverse.apply(ledger, &mut changes);
+        let applied = Applied {
+            action: self.action,
+            changes,
+        };
+        (applied, Step::new(self.action, inverse))
+    }
+}
+
+impl Inverse {
+    /// Reverse onto the ledger, noting each affected transaction in `changes`,
+    /// and return what undoes this in turn.
+    fn apply(self, ledger: &mut Ledger, changes: &mut Vec<(ChangeKind, Transaction)>) -> Inverse {
+        match self {
+            Inverse::Remove(ids) => {
+                let ids: HashSet<Uuid> = ids.into_iter().collect();
+                let removed = ledger.remove(&ids);
+                changes.extend(removed.iter().map(|t| (ChangeKind::Removed, t.clone())));
+                Inverse::Insert(removed)
+            }
+            Inverse::Insert(txs) => {
+                let ids = txs.iter().map(|t| t.id).collect();
+                ledger.extend(txs.iter().cloned());
+                changes.extend(txs.into_iter().map(|t| (ChangeKind::Created, t)));
+                Inverse::Remove(ids)
+            }
+            Inverse::Revert(saved) => {
+                let mut reverted = Vec::with_capacity(saved.len());
+                let mut replaced = Vec::with_capacity(saved.len());
+                for old in saved {
+                    let id = old.id;
+                    ledger.update(&id, |tx| {
+                        replaced.push(tx.clone());
+                        // the version keeps counting up so stale If-Match headers still fail
+                        let version = tx.version;
+                        *tx = old;
+                        tx.version = version;
+                        tx.bump_version();
+                        reverted.push(tx.clone());
+                    });
+                }
+                changes.extend(reverted.into_iter().map(|t| (ChangeKind::Updated, t)));
+                Inverse::Revert(replaced)
+            }
+            Inverse::All(parts) => {
+                let mut undone: Vec<Inverse> = parts
+                    .into_iter()
+                    .map(|p| p.apply(ledger, changes))
+                    .collect();
+                undone.reverse();
+                Inverse::All(undone)
+            }
+        }
+    }
+}
+
+/// Bounded undo and redo stacks. Callers hold the ledger write lock while
+/// recording or applying, so the stacks stay in step with the ledger.
+pub struct UndoHistory {
+    capacity: usize,
+    stacks: Mutex<Stacks>,
+}
+
+#[derive(Default)]
+struct Stacks {
+    undo: VecDeque<Step>,
+    redo: Vec<Step>,
+}
+
+impl UndoHistory {
+    /// Keep at most `capacity` steps; 0 turns undo off.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            stacks: Mutex::new(Stacks::default()),
+        }
+    }
+
+    /// Remember a fresh mutation. Anything previously undone can no longer be redone.
+    pub fn record(&self, step: Step) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut stacks = self.stacks.lock().unwrap_or_else(|e| e.into_inner());
+        stacks.redo.clear();
+        push_bounded(&mut stacks.undo, step, self.capacity);
+    }
+
+    /// Reverse the newest step, or None if there is nothing to undo.
+    pub fn undo(&self, ledger: &mut Ledger) -> Option<Applied> {
+        let mut stacks = self.stacks.lock().unwrap_or_else(|e| e.into_inner());
+        let step = stacks.undo.pop_back()?;
+        let (applied, redo) = step.apply(ledger);
+        stacks.redo.push(redo);
+        Some(applied)
+    }
+
+    /// Re-apply the most recently undone step.
+    pub fn redo(&self, ledger: &mut Ledger) -> Option<Applied> {
+        let mut stacks = self.stacks.lock().unwrap_or_else(|e| e.into_inner());
+        let step = stacks.redo.pop()?;
+        let (applied, undo) = step.apply(ledger);
+        push_bounded(&mut stacks.undo, undo, self.capacity);
+        Some(applied)
+    }
+
+    /// Forget everything, e.g. after the whole ledger was replaced.
+    pub fn clear(&self) {
+        let mut stacks = self.stacks.lock().unwrap_or_else(|e| e.into_inner());
+        *stacks = Stacks::default();
+    }
+}
+
+fn push_bounded(steps: &mut VecDeque<Step>, step: Step, capacity: usize) {
+    if steps.len() == capacity {
+        steps.pop_front();
+    }
+    steps.push_back(step);
+}
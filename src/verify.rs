@@ -0,0 +1,79 @@
+use crate::{Transaction, is_valid_currency, round_amount};
+use serde::Serialize;
+use std::collections::HashSet;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+/// Something about one stored transaction that the API would never have written.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct Finding {
+    /// e.g. "duplicate_id", "empty_user", "future_timestamp"
+    pub check: &'static str,
+    pub id: Uuid,
+    pub message: String,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct VerifyReport {
+    pub checked: usize,
+    pub ok: bool,
+    pub findings: Vec<Finding>,
+}
+
+/// Scan every row for damage a hand edit of the storage file could introduce.
+/// Amounts are decimals, so NaN and infinity can't occur; a negative amount is
+/// a credit, so the sign alone isn't a finding either.
+pub fn verify(txs: &[Transaction], now: u64) -> VerifyReport {
+    let mut findings = Vec::new();
+    let mut seen = HashSet::new();
+    for tx in txs {
+        let mut flag = |check, message: String| {
+            findings.push(Finding {
+                check,
+                id: tx.id,
+                message,
+            })
+        };
+        if !seen.insert(tx.id) {
+            flag(
+                "duplicate_id",
+                "id is used by more than one transaction".to_string(),
+            );
+        }
+        if tx.user.trim().is_empty() {
+            flag("empty_user", "user is empty".to_string());
+        }
+        if tx.item.trim().is_empty() {
+            flag("empty_item", "item is empty".to_string());
+        }
+        if !is_valid_currency(&tx.currency) {
+            flag(
+                "invalid_currency",
+                format!("{:?} is not a three-letter ISO 4217 code", tx.currency),
+            );
+        } else if round_amount(tx.amount, &tx.currency) != tx.amount {
+            flag(
+                "unrounded_amount",
+                format!(
+                    "{} has more decimal places than {} uses",
+                    tx.amount, tx.currency
+                ),
+            );
+        }
+        if tx.timestamp > now {
+            flag(
+                "future_timestamp",
+                format!(
+                    "timestamp {} is {}s in the future",
+                    tx.timestamp,
+                    tx.timestamp - now
+                ),
+            );
+        }
+    }
+    VerifyReport {
+        checked: txs.len(),
+        ok: findings.is_empty(),
+        findings,
+    }
+}
@@ -0,0 +1,184 @@
+use crate::Transaction;
+use crate::ledger::Ledger;
+use crate::storage::{self, Storage};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::io;
+use tokio::fs::{self, OpenOptions};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::{Mutex, MutexGuard};
+use uuid::Uuid;
+
+/// One logged change, recorded as the row's resulting state so replaying a
+/// record the storage file already has is harmless.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+pub enum WalRecord {
+    Put { transaction: Box<Transaction> },
+    Remove { id: Uuid },
+}
+
+impl WalRecord {
+    /// Records for every row the ledger changed since the last call.
+    pub fn drain(ledger: &mut Ledger) -> Vec<WalRecord> {
+        ledger
+            .take_changed()
+            .into_iter()
+            .map(|id| match ledger.get(&id) {
+                Some(tx) => WalRecord::Put {
+                    transaction: Box::new(tx.clone()),
+                },
+                None => WalRecord::Remove { id },
+            })
+            .collect()
+    }
+}
+
+/// Changes that are in memory but not yet in storage, one JSON object per
+/// line. Emptied whenever a full save succeeds.
+pub struct WriteAheadLog {
+    file_path: String,
+    /// held by appenders, and by savers from snapshot until truncation
+    lock: Mutex<()>,
+}
+
+/// Holds the log from before the ledger is drained until the records are
+/// synced, so concurrent persists append in the order they drained and no
+/// checkpoint can save and truncate in between and lose a drained record.
+pub struct Appender<'a> {
+    wal: &'a WriteAheadLog,
+    _guard: MutexGuard<'a, ()>,
+}
+
+/// Blocks appends until dropped, so nothing logged after a snapshot was
+/// taken is truncated along with what the snapshot already covers. Storage
+/// is only written through one of these, which also keeps saves from
+/// overlapping: each waits for the last to finish, then snapshots, so a
+/// stale snapshot can never land after a newer one.
+pub struct Checkpoint<'a> {
+    wal: &'a WriteAheadLog,
+    _guard: MutexGuard<'a, ()>,
+}
+
+impl WriteAheadLog {
+    pub fn new(file_path: impl Into<String>) -> Self {
+        Self {
+            file_path: file_path.into(),
+            lock: Mutex::new(()),
+        }
+    }
+
+    pub fn file_path(&self) -> &str {
+        &self.file_path
+    }
+
+    /// Every readable record, oldest first.
+    pub async fn read(&self) -> io::Result<Vec<WalRecord>> {
+        let data = match fs::read_to_string(&self.file_path).await {
+            Ok(data) => data,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e),
+        };
+        let mut records = Vec::new();
+        for (n, line) in data.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            match serde_json::from_str(line) {
+                Ok(record) => records.push(record),
+                // a line torn by a crash mid-append was never acknowledged
+                Err(e) => tracing::warn!(
+                    error = %e,
+                    file = %self.file_path,
+                    line = n + 1,
+                    "Skipping unreadable write-ahead log record"
+                ),
+            }
+        }
+        Ok(records)
+    }
+
+    /// Take this before draining the ledger's changes for an append.
+    pub async fn appender(&self) -> Appender<'_> {
+        Appender {
+            wal: self,
+            _guard: self.lock.lock().await,
+        }
+    }
+
+    /// Take this before snapshotting the ledger for a full save.
+    pub async fn checkpoint(&self) -> Checkpoint<'_> {
+        Checkpoint {
+            wal: self,
+            _guard: self.lock.lock().await,
+        }
+    }
+}
+
+impl Appender<'_> {
+    /// Append `records` and sync them to disk before returning.
+    pub async fn append(self, records: &[WalRecord]) -> io::Result<()> {
+        if records.is_empty() {
+            return Ok(());
+        }
+        let mut data = Vec::new();
+        for record in records {
+            serde_json::to_writer(&mut data, record)?;
+            data.push(b'\n');
+        }
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.wal.file_path)
+            .await?;
+        file.write_all(&data).await?;
+        file.sync_data().await
+    }
+}
+
+impl Checkpoint<'_> {
+    /// Write `txs` as the full stored state, retrying transient failures.
+    pub async fn save(
+        &self,
+        storage: &(dyn Storage + Send + Sync),
+        txs: &[Transaction],
+    ) -> io::Result<()> {
+        storage::save_with_retry(storage, txs).await
+    }
+
+    /// The snapshot is saved; drop everything logged so far.
+    pub async fn truncate(self) -> io::Result<()> {
+        match fs::remove_file(&self.wal.file_path).await {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Apply `records` on top of what storage loaded, returning how many changed
+/// anything; records storage already reflects are no-ops.
+pub fn replay(ledger: &mut Ledger, records: Vec<WalRecord>) -> usize {
+    let mut applied = 0;
+    for record in records {
+        match record {
+            WalRecord::Put { transaction } => match ledger.get(&transaction.id) {
+                Some(current) if *current == *transaction => {}
+                Some(_) => {
+                    let id = transaction.id;
+                    ledger.update(&id, |tx| *tx = *transaction);
+                    applied += 1;
+                }
+                None => {
+                    ledger.push(*transaction);
+                    applied += 1;
+                }
+            },
+            WalRecord::Remove { id } => {
+                if !ledger.remove(&HashSet::from([id])).is_empty() {
+                    applied += 1;
+                }
+            }
+        }
+    }
+    applied
+}